@@ -0,0 +1,184 @@
+mod wind;
+
+use std::env;
+use std::fs;
+use std::process::{Command, ExitCode};
+use std::time::{Duration, SystemTime};
+use anyhow::{anyhow, Context, Error};
+use chrono::NaiveDate;
+use clap::{Parser, ValueEnum};
+
+use quant_test::cache::{identity, is_fresh, CachedTicks, CachedTransactions};
+use quant_test::reader::{TickReader, TransactionReader};
+use quant_test::schema::Schema;
+
+/// Compares the serde reader of the Wind layout with the streaming reader and the binary cache on a pair of files
+#[derive(Debug, Parser)]
+struct Args {
+    #[arg(long, default_value = "./resource/2021-06-04/601012.SH.Tick.csv")]
+    ticks: String,
+    #[arg(long, default_value = "./resource/2021-06-04/601012.SH.Transaction.csv")]
+    transactions: String,
+    /// Trading date of the data, by default taken from a date column or the file path
+    #[arg(long)]
+    date: Option<NaiveDate>,
+    /// Times each reader reads the files, the fastest round counts
+    #[arg(long, default_value_t = 3)]
+    rounds: usize,
+    /// Schema of the files, `wind` or a TOML file describing it; the serde reader is Wind only
+    #[arg(long, default_value = "wind")]
+    schema: String,
+    /// Run one reader and print its results, the bench runs each reader in a process of its own this way
+    #[arg(long, hide = true)]
+    reader: Option<Reader>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Reader {
+    Serde,
+    Streaming,
+    Cache,
+}
+
+// peak resident memory of the process, Linux only
+fn peak_memory() -> Option<String> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .map(|peak| peak.trim().to_string())
+}
+
+// the fastest of `rounds` reads and the rows read
+fn time_rounds(rounds: usize, read: impl Fn() -> Result<usize, Error>) -> Result<(Duration, usize), Error> {
+    let mut fastest = Duration::MAX;
+    let mut rows = 0;
+    for _ in 0..rounds.max(1) {
+        let start = SystemTime::now();
+        rows = read()?;
+        fastest = fastest.min(SystemTime::now().duration_since(start)?);
+    }
+
+    Ok((fastest, rows))
+}
+
+fn count<T>(rows: impl Iterator<Item = Result<T, Error>>) -> Result<usize, Error> {
+    rows.into_iter().try_fold(0, |count, row| row.map(|_| count + 1))
+}
+
+// what one reader measured: the fastest read and rows of each file, `None` for a file without a fresh cache
+struct Measure {
+    ticks: Option<(Duration, usize)>,
+    transactions: Option<(Duration, usize)>,
+    peak: Option<String>,
+}
+
+// runs in the child process, the results go to the parent as `ticks`, `transactions` and `peak` lines
+fn measure(args: &Args, reader: Reader) -> Result<(), Error> {
+    let (date, rounds, schema) = (args.date, args.rounds, Schema::load(&args.schema)?);
+    let (ticks, transactions) = match reader {
+        Reader::Serde => (
+            Some(time_rounds(rounds, || Ok(wind::read_ticks_from_file(&args.ticks, date)?.len()))?),
+            Some(time_rounds(rounds, || Ok(wind::read_transactions_from_file(&args.transactions, date)?.len()))?),
+        ),
+        Reader::Streaming => (
+            Some(time_rounds(rounds, || count(TickReader::open(&args.ticks, date, &schema.ticks)?))?),
+            Some(time_rounds(rounds, || count(TransactionReader::open(&args.transactions, date, &schema.transactions)?))?),
+        ),
+        // caches written by `convert`
+        Reader::Cache => (
+            match is_fresh(&args.ticks, identity(&schema.ticks), date) {
+                true => Some(time_rounds(rounds, || count(CachedTicks::open(&args.ticks)?))?),
+                false => None,
+            },
+            match is_fresh(&args.transactions, identity(&schema.transactions), date) {
+                true => Some(time_rounds(rounds, || count(CachedTransactions::open(&args.transactions)?))?),
+                false => None,
+            },
+        ),
+    };
+    for (name, result) in [("ticks", ticks), ("transactions", transactions)] {
+        if let Some((time, rows)) = result {
+            println!("{} {} {}", name, time.as_nanos(), rows);
+        }
+    }
+    if let Some(peak) = peak_memory() {
+        println!("peak {}", peak);
+    }
+
+    Ok(())
+}
+
+// runs `reader` in a process of its own, since the peak memory of a process never goes down
+fn spawn(reader: Reader) -> Result<Measure, Error> {
+    let name = format!("{:?}", reader).to_lowercase();
+    let output = Command::new(env::current_exe()?)
+        .args(env::args().skip(1))
+        .args(["--reader", name.as_str()])
+        .output()
+        .with_context(|| format!("run the {} reader", name))?;
+    if !output.status.success() {
+        return Err(anyhow!("{} reader: {}", name, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let mut measure = Measure { ticks: None, transactions: None, peak: None };
+    for line in String::from_utf8(output.stdout)?.lines() {
+        let (key, value) = line.split_once(' ').ok_or_else(|| anyhow!("{} reader: `{}`", name, line))?;
+        let read = || -> Result<(Duration, usize), Error> {
+            let (nanos, rows) = value.split_once(' ').ok_or_else(|| anyhow!("{} reader: `{}`", name, line))?;
+            Ok((Duration::from_nanos(nanos.parse()?), rows.parse()?))
+        };
+        match key {
+            "ticks" => measure.ticks = Some(read()?),
+            "transactions" => measure.transactions = Some(read()?),
+            _ => measure.peak = Some(value.to_string()),
+        }
+    }
+
+    Ok(measure)
+}
+
+fn bench(args: &Args) -> Result<(), Error> {
+    let (serde, streaming, cache) = (spawn(Reader::Serde)?, spawn(Reader::Streaming)?, spawn(Reader::Cache)?);
+
+    let speedup = |serde: Duration, other: Duration| serde.as_secs_f64() / other.as_secs_f64().max(f64::EPSILON);
+    println!("[Bench] fastest of {} rounds", args.rounds.max(1));
+    let results = [
+        ("ticks", serde.ticks, streaming.ticks, cache.ticks),
+        ("transactions", serde.transactions, streaming.transactions, cache.transactions),
+    ];
+    for (name, serde, streaming, cache) in results {
+        let ((serde, _), (streaming, rows)) = (serde.unwrap(), streaming.unwrap());
+        println!(
+            "{}: {} rows\n\tserde: {:?}\n\tstreaming: {:?} ({:.1}x)",
+            name, rows, serde, streaming, speedup(serde, streaming),
+        );
+        if let Some((cache, _)) = cache {
+            println!("\tcache: {:?} ({:.1}x)", cache, speedup(serde, cache));
+        }
+    }
+    println!("peak memory:");
+    for (name, measure) in [("serde", serde), ("streaming", streaming), ("cache", cache)] {
+        // a reader that read nothing, the cache without `convert`, has nothing to show
+        let read = measure.ticks.is_some() || measure.transactions.is_some();
+        if let Some(peak) = measure.peak.filter(|_| read) {
+            println!("\t{}: {}", name, peak);
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let result = match args.reader {
+        Some(reader) => measure(&args, reader),
+        None => bench(&args),
+    };
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {:?}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::convert::TryFrom;
+use std::path::Path;
+use anyhow::{anyhow, Error};
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use quant_test::raw_data::TradingDate;
+use quant_test::session::Phase;
+use quant_test::tick::Tick;
+use quant_test::transaction::{FunctionCode, OrderKind, Transaction};
+use quant_test::utils::{time_parser, Direction};
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+struct TickRawData {
+    #[serde(rename = "chWindCode")]
+    pub ch_wind_code: String,
+    // YYYYMMDD, optional
+    #[serde(rename = "nActionDay", default)]
+    pub n_action_day: Option<usize>,
+    #[serde(rename = "nTime")]
+    pub n_time: usize,
+    #[serde(rename = "Status")]
+    pub status: usize,
+    #[serde(rename = "PreClose")]
+    pub pre_close: usize,
+    #[serde(rename = "Open")]
+    pub open: usize,
+    #[serde(rename = "High")]
+    pub high: usize,
+    #[serde(rename = "Low")]
+    pub low: usize,
+    #[serde(rename = "nPrice")]
+    pub n_price: usize,
+    #[serde(rename = "nAskPrice1")]
+    pub n_ask_price_1: usize,
+    #[serde(rename = "nAskPrice2")]
+    pub n_ask_price_2: usize,
+    #[serde(rename = "nAskPrice3")]
+    pub n_ask_price_3: usize,
+    #[serde(rename = "nAskPrice4")]
+    pub n_ask_price_4: usize,
+    #[serde(rename = "nAskPrice5")]
+    pub n_ask_price_5: usize,
+    #[serde(rename = "nAskPrice6")]
+    pub n_ask_price_6: usize,
+    #[serde(rename = "nAskPrice7")]
+    pub n_ask_price_7: usize,
+    #[serde(rename = "nAskPrice8")]
+    pub n_ask_price_8: usize,
+    #[serde(rename = "nAskPrice9")]
+    pub n_ask_price_9: usize,
+    #[serde(rename = "nAskPrice10")]
+    pub n_ask_price_10: usize,
+    #[serde(rename = "nAskVolume1")]
+    pub n_ask_volume_1: usize,
+    #[serde(rename = "nAskVolume2")]
+    pub n_ask_volume_2: usize,
+    #[serde(rename = "nAskVolume3")]
+    pub n_ask_volume_3: usize,
+    #[serde(rename = "nAskVolume4")]
+    pub n_ask_volume_4: usize,
+    #[serde(rename = "nAskVolume5")]
+    pub n_ask_volume_5: usize,
+    #[serde(rename = "nAskVolume6")]
+    pub n_ask_volume_6: usize,
+    #[serde(rename = "nAskVolume7")]
+    pub n_ask_volume_7: usize,
+    #[serde(rename = "nAskVolume8")]
+    pub n_ask_volume_8: usize,
+    #[serde(rename = "nAskVolume9")]
+    pub n_ask_volume_9: usize,
+    #[serde(rename = "nAskVolume10")]
+    pub n_ask_volume_10: usize,
+    #[serde(rename = "nBidPrice1")]
+    pub n_bid_price_1: usize,
+    #[serde(rename = "nBidPrice2")]
+    pub n_bid_price_2: usize,
+    #[serde(rename = "nBidPrice3")]
+    pub n_bid_price_3: usize,
+    #[serde(rename = "nBidPrice4")]
+    pub n_bid_price_4: usize,
+    #[serde(rename = "nBidPrice5")]
+    pub n_bid_price_5: usize,
+    #[serde(rename = "nBidPrice6")]
+    pub n_bid_price_6: usize,
+    #[serde(rename = "nBidPrice7")]
+    pub n_bid_price_7: usize,
+    #[serde(rename = "nBidPrice8")]
+    pub n_bid_price_8: usize,
+    #[serde(rename = "nBidPrice9")]
+    pub n_bid_price_9: usize,
+    #[serde(rename = "nBidPrice10")]
+    pub n_bid_price_10: usize,
+    #[serde(rename = "nBidVolume1")]
+    pub n_bid_volume_1: usize,
+    #[serde(rename = "nBidVolume2")]
+    pub n_bid_volume_2: usize,
+    #[serde(rename = "nBidVolume3")]
+    pub n_bid_volume_3: usize,
+    #[serde(rename = "nBidVolume4")]
+    pub n_bid_volume_4: usize,
+    #[serde(rename = "nBidVolume5")]
+    pub n_bid_volume_5: usize,
+    #[serde(rename = "nBidVolume6")]
+    pub n_bid_volume_6: usize,
+    #[serde(rename = "nBidVolume7")]
+    pub n_bid_volume_7: usize,
+    #[serde(rename = "nBidVolume8")]
+    pub n_bid_volume_8: usize,
+    #[serde(rename = "nBidVolume9")]
+    pub n_bid_volume_9: usize,
+    #[serde(rename = "nBidVolume10")]
+    pub n_bid_volume_10: usize,
+    #[serde(rename = "nMatchItems")]
+    pub n_match_items: usize,
+    #[serde(rename = "TotalVolume")]
+    pub total_volume: usize,
+    #[serde(rename = "TotalTurnover")]
+    pub total_turnover: usize,
+    #[serde(rename = "TotalBidVolume")]
+    pub total_bid_volume: usize,
+    #[serde(rename = "TotalAskVolume")]
+    pub total_ask_volume: usize,
+    #[serde(rename = "WeightedAvgBidPrice")]
+    pub weighted_avg_bid_price: usize,
+    #[serde(rename = "WeightedAvgAskPrice")]
+    pub weighted_avg_ask_price: usize,
+    #[serde(rename = "IOPV")]
+    pub iopv: usize,
+    #[serde(rename = "YieldToMaturity")]
+    pub yield_to_maturity: usize,
+    #[serde(rename = "HighLimited")]
+    pub high_limited: usize,
+    #[serde(rename = "LowLimited")]
+    pub low_limited: usize,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+struct TrxRawData {
+    #[serde(rename = "Tkr")]
+    pub tkr: String,
+    // YYYYMMDD, optional
+    #[serde(rename = "Date", default)]
+    pub date: Option<usize>,
+    #[serde(rename = "Time")]
+    pub time: usize,
+    #[serde(rename = "Index")]
+    pub index: usize,
+    #[serde(rename = "Price")]
+    pub price: usize,
+    #[serde(rename = "Volume")]
+    pub volume: usize,
+    #[serde(rename = "Turnover")]
+    pub turnover: usize,
+    #[serde(rename = "BSFlag")]
+    pub flag: String,
+    #[serde(rename = "OrderKind")]
+    pub order_kind: String,
+    #[serde(rename = "FunctionCode")]
+    pub function_code: String,
+    #[serde(rename = "AskOrder")]
+    pub ask_order: usize,
+    #[serde(rename = "BidOrder")]
+    pub bid_order: usize,
+}
+
+impl TickRawData {
+    fn into_tick(self, date: NaiveDate) -> Result<Tick, Error> {
+        let raw = self;
+        Ok(Tick {
+            symbol: raw.ch_wind_code,
+            timestamp: time_parser(date, raw.n_time)?,
+            new_price: raw.n_price,
+            asks: vec![
+                (raw.n_ask_price_1, raw.n_ask_volume_1),
+                (raw.n_ask_price_2, raw.n_ask_volume_2),
+                (raw.n_ask_price_3, raw.n_ask_volume_3),
+                (raw.n_ask_price_4, raw.n_ask_volume_4),
+                (raw.n_ask_price_5, raw.n_ask_volume_5),
+                (raw.n_ask_price_6, raw.n_ask_volume_6),
+                (raw.n_ask_price_7, raw.n_ask_volume_7),
+                (raw.n_ask_price_8, raw.n_ask_volume_8),
+                (raw.n_ask_price_9, raw.n_ask_volume_9),
+                (raw.n_ask_price_10, raw.n_ask_volume_10),
+            ],
+            bids: vec![
+                (raw.n_bid_price_1, raw.n_bid_volume_1),
+                (raw.n_bid_price_2, raw.n_bid_volume_2),
+                (raw.n_bid_price_3, raw.n_bid_volume_3),
+                (raw.n_bid_price_4, raw.n_bid_volume_4),
+                (raw.n_bid_price_5, raw.n_bid_volume_5),
+                (raw.n_bid_price_6, raw.n_bid_volume_6),
+                (raw.n_bid_price_7, raw.n_bid_volume_7),
+                (raw.n_bid_price_8, raw.n_bid_volume_8),
+                (raw.n_bid_price_9, raw.n_bid_volume_9),
+                (raw.n_bid_price_10, raw.n_bid_volume_10),
+            ],
+            // limit prices are quoted in 1/1000 CNY
+            high_limited: raw.high_limited * 10,
+            low_limited: raw.low_limited * 10,
+            // refined by `Calendar::assign_phase`
+            phase: Phase::from_status(raw.status),
+        })
+    }
+}
+
+impl TrxRawData {
+    fn into_transaction(self, date: NaiveDate) -> Result<Transaction, Error> {
+        let raw = self;
+        let direction = Direction::try_from(raw.flag.as_str())
+            .map_err(|e| anyhow!("transaction #{}: {}", raw.index, e))?;
+        let index = raw.index;
+
+        Ok(Transaction {
+            symbol: raw.tkr,
+            timestamp: time_parser(date, raw.time)
+                .map_err(|e| anyhow!("transaction #{}: {}", index, e))?,
+            index,
+            price: raw.price,
+            volume: raw.volume,
+            direction,
+            order_kind: OrderKind::try_from(raw.order_kind.as_str())
+                .map_err(|e| anyhow!("transaction #{}: order kind: {}", index, e))?,
+            function_code: FunctionCode::try_from(raw.function_code.as_str())
+                .map_err(|e| anyhow!("transaction #{}: function code: {}", index, e))?,
+            ask_order: raw.ask_order,
+            bid_order: raw.bid_order,
+        })
+    }
+}
+
+// the YYYYMMDD of a date column, `None` where it is left out or zero
+fn column_date(yyyymmdd: Option<usize>) -> Result<Option<NaiveDate>, Error> {
+    yyyymmdd
+        .filter(|date| *date > 0)
+        .map(|date| NaiveDate::parse_from_str(&date.to_string(), "%Y%m%d").map_err(|e| anyhow!("date {}: {}", date, e)))
+        .transpose()
+}
+
+// the serde reader of the Wind layout holding the whole file, the streaming readers are measured against it
+pub fn read_ticks_from_file(path: &str, date: Option<NaiveDate>) -> Result<Vec<Tick>, Error> {
+    let date = TradingDate::new(date, path);
+    let mut reader = csv::Reader::from_path(Path::new(path))?;
+    let ticks = reader
+        .deserialize::<TickRawData>()
+        .map(|raw_data| {
+            let raw_data = raw_data?;
+            let date = date.resolve_with(|| column_date(raw_data.n_action_day))?;
+            raw_data.into_tick(date)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(ticks)
+}
+
+pub fn read_transactions_from_file(path: &str, date: Option<NaiveDate>) -> Result<Vec<Transaction>, Error> {
+    let date = TradingDate::new(date, path);
+    let mut reader = csv::Reader::from_path(Path::new(path))?;
+    let transactions = reader
+        .deserialize::<TrxRawData>()
+        .map(|raw_data| {
+            let raw_data = raw_data?;
+            let date = date.resolve_with(|| column_date(raw_data.date))?;
+            raw_data.into_transaction(date)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(transactions)
+}
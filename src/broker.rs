@@ -0,0 +1,660 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use anyhow::{anyhow, Error};
+use chrono::TimeDelta;
+use serde::{Deserialize, Serialize};
+
+use crate::event::Event;
+use crate::fee::{FeeBreakdown, FeeModel};
+use crate::impact::{Impact, ImpactConfig};
+use crate::order_book::{OrderBook, Sweep};
+use crate::portfolio::Portfolio;
+use crate::queue::QueueModel;
+use crate::session::{Calendar, Phase};
+use crate::strategy::StrategyConfig;
+use crate::tick::Tick;
+use crate::transaction::Transaction;
+use crate::utils::{serialize_time, time_origin, time_unparser, Direction, Price, Symbol, Time, Value, Volume};
+
+pub type OrderId = usize;
+pub type TimerToken = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Liquidity {
+    // taken from the book by a market order
+    Active,
+    // resting limit order hit by a transaction
+    Passive,
+}
+
+/// Market order types, all bounded by the daily limits and the price cage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderType {
+    // sweeps the whole displayed book, the rest follows `market_remainder`
+    FullDepth,
+    // SSE best five levels immediate or cancel
+    BestFiveCancel,
+    // SSE best five levels, the rest becomes a limit order at the last traded price
+    BestFiveToLimit,
+    // SSE own side best price, rests as a limit order at it
+    OwnBest,
+    // SSE counterparty best price, takes that level and rests the rest as a limit order at its price
+    CounterpartyBest,
+}
+
+/// What happens to the part of a full depth market order the book cannot fill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemainderPolicy {
+    Cancel,
+    // rests as a limit order at the deepest level swept
+    RestAsLimit,
+    // is sent again as a market order on the next tick
+    RetryNextTick,
+}
+
+/// What happens to a market order while the stock is sealed at the limit against it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SealedPolicy {
+    Reject,
+    // rests as a limit order at the limit price, behind the sealed queue
+    Queue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remainder {
+    None,
+    Cancelled,
+    Resting(Price),
+    Retrying,
+}
+
+/// Outcome of a market order as it is submitted, its fills arrive by `on_fill`.
+#[derive(Debug, Clone, Copy)]
+pub struct MarketOrder {
+    pub id: OrderId,
+    pub filled_volume: Volume,
+    pub unfilled_volume: Volume,
+    pub average_price: Price,
+    pub remainder: Remainder,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Fill {
+    pub order_id: OrderId,
+    pub symbol: Symbol,
+    #[serde(serialize_with = "serialize_time")]
+    pub timestamp: Time,
+    pub direction: Direction,
+    pub liquidity: Liquidity,
+    pub price: Price,
+    pub volume: Volume,
+    pub value: Value,
+    pub fee: FeeBreakdown,
+}
+
+// the accounting of an order resting in the book
+#[derive(Debug)]
+struct LimitOrder {
+    symbol: Symbol,
+    direction: Direction,
+    price: Price,
+    traded_value: Value,
+    // cash still reserved for the unfilled part of a buy, fees included
+    frozen_cash: Value,
+}
+
+// the rest of a market order waiting for the next tick
+#[derive(Debug)]
+struct PendingMarketOrder {
+    id: OrderId,
+    symbol: Symbol,
+    direction: Direction,
+    volume: Volume,
+    traded_value: Value,
+}
+
+// a limit order entered during an auction, it waits outside the book for the uncross
+#[derive(Debug)]
+struct AuctionOrder {
+    id: OrderId,
+    direction: Direction,
+    price: Price,
+    volume: Volume,
+}
+
+// the market state of one symbol
+#[derive(Debug)]
+struct Market {
+    tick: Option<Tick>,
+    last_price: Price,
+    book: OrderBook,
+    impact: Impact,
+    auction_orders: Vec<AuctionOrder>,
+}
+
+/// The strategy's view of the simulated exchange: market state of every symbol, order entry and timers.
+#[derive(Debug)]
+pub struct Broker<'a> {
+    now: Time,
+    markets: BTreeMap<Symbol, Market>,
+    queue_model: QueueModel,
+    impact: ImpactConfig,
+    portfolio: Portfolio,
+    rejected_buys: usize,
+    rejected_sells: usize,
+    // market orders that met the stock sealed at the limit
+    blocked_buys: usize,
+    blocked_sells: usize,
+    fee_model: &'a dyn FeeModel,
+    market_remainder: RemainderPolicy,
+    price_cage: bool,
+    limit_sealed: SealedPolicy,
+    pending_market_orders: Vec<PendingMarketOrder>,
+    next_order_id: OrderId,
+    limit_orders: HashMap<OrderId, LimitOrder>,
+    calendar: Calendar,
+    timers: BinaryHeap<Reverse<(Time, TimerToken)>>,
+    fills: Vec<Fill>,
+    dispatched_fills: usize,
+    // closing order -> opening order
+    links: HashMap<OrderId, OrderId>,
+}
+
+impl<'a> Broker<'a> {
+    pub fn new(config: &StrategyConfig, fee_model: &'a dyn FeeModel) -> Self {
+        Self {
+            now: time_origin(),
+            markets: BTreeMap::new(),
+            queue_model: config.queue_model,
+            impact: config.impact.clone(),
+            portfolio: Portfolio::new(config),
+            rejected_buys: 0,
+            rejected_sells: 0,
+            blocked_buys: 0,
+            blocked_sells: 0,
+            fee_model,
+            market_remainder: config.market_remainder,
+            price_cage: config.price_cage,
+            limit_sealed: config.limit_sealed,
+            pending_market_orders: Vec::new(),
+            next_order_id: 0,
+            limit_orders: HashMap::new(),
+            calendar: config.calendar.clone(),
+            timers: BinaryHeap::new(),
+            fills: Vec::new(),
+            dispatched_fills: 0,
+            links: HashMap::new(),
+        }
+    }
+
+    pub fn now(&self) -> Time {
+        self.now
+    }
+
+    /// Latest snapshot of `symbol` seen by the engine.
+    pub fn tick(&self, symbol: &str) -> Option<&Tick> {
+        self.markets.get(symbol).and_then(|market| market.tick.as_ref())
+    }
+
+    pub fn phase(&self, symbol: &str) -> Phase {
+        self.tick(symbol).map_or(Phase::PreOpen, |tick| tick.phase)
+    }
+
+    // the symbols seen so far
+    pub fn symbols(&self) -> impl Iterator<Item = &Symbol> {
+        self.markets.keys()
+    }
+
+    // the last traded price of `symbol`, 0 before its first tick
+    pub fn mark(&self, symbol: &str) -> Price {
+        self.markets.get(symbol).map_or(0, |market| market.last_price)
+    }
+
+    pub fn portfolio(&self) -> &Portfolio {
+        &self.portfolio
+    }
+
+    /// Schedules `on_timer(token)` on the first event later than `at`.
+    pub fn set_timer(&mut self, at: Time, token: TimerToken) {
+        self.timers.push(Reverse((at, token)));
+    }
+
+    /// Schedules `on_timer(token)` once `delay` of continuous trading has passed, breaks not counted.
+    pub fn set_timer_after(&mut self, delay: TimeDelta, token: TimerToken) {
+        let at = self.calendar.advance(self.now, delay);
+        self.set_timer(at, token);
+    }
+
+    fn market_tick(&self, symbol: &str, kind: &str) -> Result<Tick, Error> {
+        self.tick(symbol)
+            .cloned()
+            .ok_or_else(|| anyhow!("{} order of {} before its first tick", kind, symbol))
+    }
+
+    pub fn submit_market_order(
+        &mut self,
+        symbol: &str,
+        order_type: OrderType,
+        direction: Direction,
+        volume: Volume,
+    ) -> Result<MarketOrder, Error> {
+        if direction == Direction::Unknown {
+            return Err(anyhow!("market order of {} {} has no direction", volume, symbol));
+        }
+        let tick = self.market_tick(symbol, "market")?;
+        if tick.sealed(direction) {
+            match direction {
+                Direction::Buy => self.blocked_buys += 1,
+                Direction::Sell => self.blocked_sells += 1,
+                Direction::Unknown => unreachable!(),
+            }
+            return match (self.limit_sealed, tick.limit_price(direction)) {
+                (SealedPolicy::Queue, Some(price)) => self.rest_market_order(symbol, direction, price, volume),
+                _ => Err(anyhow!(
+                    "{:?} market order of {} at {} is blocked by the limit",
+                    direction, symbol, time_unparser(tick.timestamp),
+                )),
+            };
+        }
+
+        let levels = match order_type {
+            OrderType::FullDepth => usize::MAX,
+            OrderType::BestFiveCancel | OrderType::BestFiveToLimit => 5,
+            OrderType::CounterpartyBest => 1,
+            OrderType::OwnBest => {
+                let price = self.markets[symbol].book.best_price(direction).ok_or_else(|| {
+                    anyhow!("own best order of {} at {} finds its side empty", symbol, time_unparser(self.now))
+                })?;
+                return self.rest_market_order(symbol, direction, price, volume);
+            }
+        };
+        let id = self.next_order_id;
+        let (sweep, value) = self.execute_market_order(id, symbol, direction, volume, levels, 0)?;
+        self.next_order_id();
+
+        let unfilled_volume = volume - sweep.volume;
+        let remainder = match (unfilled_volume, order_type, self.market_remainder) {
+            (0, _, _) => Remainder::None,
+            (_, OrderType::BestFiveCancel, _) | (_, OrderType::FullDepth, RemainderPolicy::Cancel) => {
+                Remainder::Cancelled
+            }
+            (_, OrderType::BestFiveToLimit, _)
+            | (_, OrderType::CounterpartyBest, _)
+            | (_, OrderType::FullDepth, RemainderPolicy::RestAsLimit) => {
+                match self.rest_limit_order(id, symbol, direction, sweep.last_price, unfilled_volume, value) {
+                    Ok(()) => Remainder::Resting(sweep.last_price),
+                    Err(_) => Remainder::Cancelled,
+                }
+            }
+            (_, OrderType::FullDepth, RemainderPolicy::RetryNextTick) => {
+                self.pending_market_orders.push(PendingMarketOrder {
+                    id,
+                    symbol: symbol.to_string(),
+                    direction,
+                    volume: unfilled_volume,
+                    traded_value: value,
+                });
+                Remainder::Retrying
+            }
+            (_, OrderType::OwnBest, _) => unreachable!(),
+        };
+
+        Ok(MarketOrder {
+            id,
+            filled_volume: sweep.volume,
+            unfilled_volume,
+            average_price: value / sweep.volume,
+            remainder,
+        })
+    }
+
+    // a market order that rests in the book whole
+    fn rest_market_order(
+        &mut self,
+        symbol: &str,
+        direction: Direction,
+        price: Price,
+        volume: Volume,
+    ) -> Result<MarketOrder, Error> {
+        let id = self.next_order_id;
+        self.rest_limit_order(id, symbol, direction, price, volume, 0)?;
+        self.next_order_id();
+
+        Ok(MarketOrder {
+            id,
+            filled_volume: 0,
+            unfilled_volume: volume,
+            average_price: 0,
+            remainder: Remainder::Resting(price),
+        })
+    }
+
+    pub fn submit_limit_order(
+        &mut self,
+        symbol: &str,
+        direction: Direction,
+        price: Price,
+        volume: Volume,
+    ) -> Result<OrderId, Error> {
+        assert_ne!(volume, 0, "volume of limit order should not be zero");
+        let id = self.next_order_id;
+        self.rest_limit_order(id, symbol, direction, price, volume, 0)?;
+        self.next_order_id();
+
+        Ok(id)
+    }
+
+    /// Cancels a resting limit order and returns its unfilled volume, or `None` if it is no longer resting.
+    pub fn cancel_order(&mut self, id: OrderId) -> Option<Volume> {
+        let symbol = &self.limit_orders.get(&id)?.symbol;
+        let market = self.markets.get_mut(symbol)?;
+        let volume = match market.auction_orders.iter().position(|order| order.id == id) {
+            Some(index) => market.auction_orders.remove(index).volume,
+            None => market.book.remove(id)?,
+        };
+        let order = self.limit_orders.remove(&id)?;
+        self.portfolio.unfreeze(&order.symbol, order.direction, volume, order.frozen_cash);
+
+        Some(volume)
+    }
+
+    pub fn rejected_buys(&self) -> usize {
+        self.rejected_buys
+    }
+
+    // sells are rejected for exceeding the sellable position or for their price
+    pub fn rejected_sells(&self) -> usize {
+        self.rejected_sells
+    }
+
+    // market buys that found the stock sealed at limit-up, they would have opened
+    pub fn blocked_buys(&self) -> usize {
+        self.blocked_buys
+    }
+
+    // market sells that found the stock sealed at limit-down, they would have closed
+    pub fn blocked_sells(&self) -> usize {
+        self.blocked_sells
+    }
+
+    // marks `order_id` as closing the position opened by `open_order_id` in the ledger
+    pub fn link_close(&mut self, order_id: OrderId, open_order_id: OrderId) {
+        self.links.insert(order_id, open_order_id);
+    }
+
+    pub fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    pub fn links(&self) -> &HashMap<OrderId, OrderId> {
+        &self.links
+    }
+
+    pub(crate) fn update(&mut self, event: Event<'_>) {
+        self.now = event.timestamp();
+        if !self.markets.contains_key(event.symbol()) {
+            self.markets.insert(event.symbol().to_string(), Market {
+                tick: None,
+                last_price: 0,
+                book: OrderBook::new(self.queue_model),
+                impact: Impact::new(&self.impact),
+                auction_orders: Vec::new(),
+            });
+            self.portfolio.track(event.symbol());
+        }
+        let market = self.markets.get_mut(event.symbol()).unwrap();
+        match event {
+            Event::Tick(tick) => {
+                let auction = market.tick
+                    .replace(tick.clone())
+                    .filter(|last| last.phase.is_auction() && last.phase != tick.phase);
+                market.last_price = tick.new_price;
+                market.book.reset(tick);
+                if let Some(auction) = auction {
+                    self.uncross(&auction, tick.new_price);
+                }
+            }
+            Event::Transaction(transaction) => market.last_price = transaction.price,
+            Event::Cancel(_) => {}
+        }
+    }
+
+    // the exchange drops the rest of day orders at the close, and shares bought today settle
+    pub(crate) fn end_day(&mut self) {
+        let mut ids = self.limit_orders.keys().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+        for id in ids {
+            self.cancel_order(id);
+        }
+        self.pending_market_orders.clear();
+        self.portfolio.settle();
+    }
+
+    pub(crate) fn pop_due_timer(&mut self) -> Option<TimerToken> {
+        match self.timers.peek() {
+            Some(Reverse((at, _))) if *at < self.now => self.timers.pop().map(|Reverse((_, token))| token),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn apply_cancel(&mut self, transaction: &Transaction) {
+        if let Some(market) = self.markets.get_mut(&transaction.symbol) {
+            market.book.apply_cancel(transaction);
+        }
+    }
+
+    // sends the rest of market orders of `symbol` again, until it fills or the portfolio rejects it
+    pub(crate) fn retry_market_orders(&mut self, symbol: &str) {
+        let (orders, others) = std::mem::take(&mut self.pending_market_orders)
+            .into_iter()
+            .partition::<Vec<_>, _>(|order| order.symbol == symbol);
+        self.pending_market_orders = others;
+        for mut order in orders {
+            let rejected = self.rejected_buys + self.rejected_sells;
+            let executed = self.execute_market_order(
+                order.id, symbol, order.direction, order.volume, usize::MAX, order.traded_value,
+            );
+            match executed {
+                Ok((sweep, value)) if sweep.volume < order.volume => {
+                    order.volume -= sweep.volume;
+                    order.traded_value += value;
+                    self.pending_market_orders.push(order);
+                }
+                Ok(_) => {}
+                Err(_) if self.rejected_buys + self.rejected_sells > rejected => {}
+                Err(_) => self.pending_market_orders.push(order),
+            }
+        }
+    }
+
+    pub(crate) fn pop_fill(&mut self) -> Option<Fill> {
+        let fill = self.fills.get(self.dispatched_fills).cloned();
+        if fill.is_some() {
+            self.dispatched_fills += 1;
+        }
+
+        fill
+    }
+
+    pub(crate) fn match_transaction(&mut self, transaction: &Transaction) {
+        let fills = match self.markets.get_mut(&transaction.symbol) {
+            Some(market) => market.book.apply_trade(transaction),
+            None => return,
+        };
+        for (id, volume, rest_volume) in fills {
+            if let Some(price) = self.limit_orders.get(&id).map(|order| order.price) {
+                self.fill_limit_order(id, price, volume, rest_volume);
+            }
+        }
+    }
+
+    // fills the orders collected during an auction at the uncrossing `price`, those at exactly
+    // that price only if their side was not left with unmatched volume, the rest goes to the book
+    fn uncross(&mut self, auction: &Tick, price: Price) {
+        let orders = match self.markets.get_mut(&auction.symbol) {
+            Some(market) => std::mem::take(&mut market.auction_orders),
+            None => return,
+        };
+        for order in orders {
+            let volume = match (order.direction, order.price.cmp(&price)) {
+                _ if price == 0 => 0,
+                (Direction::Buy, Ordering::Greater) | (Direction::Sell, Ordering::Less) => order.volume,
+                (_, Ordering::Equal) if auction.unmatched_side() != Some(order.direction) => order.volume,
+                _ => 0,
+            };
+            if volume > 0 {
+                self.fill_limit_order(order.id, price, volume, order.volume - volume);
+            }
+            if order.volume > volume {
+                let market = self.markets.get_mut(&auction.symbol).unwrap();
+                market.book.insert(order.id, order.direction, order.price, order.volume - volume);
+            }
+        }
+    }
+
+    fn fill_limit_order(&mut self, id: OrderId, price: Price, volume: Volume, rest_volume: Volume) {
+        let order = match self.limit_orders.get_mut(&id) {
+            Some(order) => order,
+            None => return,
+        };
+        let value = price * volume;
+        let fee = self.fee_model.fee(order.direction, value, order.traded_value);
+        let released_cash = match rest_volume {
+            0 => order.frozen_cash,
+            _ => (value + fee.total()).min(order.frozen_cash),
+        };
+        order.traded_value += value;
+        order.frozen_cash -= released_cash;
+        let fill = Fill {
+            order_id: id,
+            symbol: order.symbol.clone(),
+            timestamp: self.now,
+            direction: order.direction,
+            liquidity: Liquidity::Passive,
+            price,
+            volume,
+            value,
+            fee,
+        };
+        if rest_volume == 0 {
+            self.limit_orders.remove(&id);
+        }
+        self.portfolio.unfreeze(&fill.symbol, fill.direction, fill.volume, released_cash);
+        self.push_fill(fill);
+    }
+
+    fn reject(&mut self, direction: Direction) {
+        match direction {
+            Direction::Buy => self.rejected_buys += 1,
+            Direction::Sell => self.rejected_sells += 1,
+            Direction::Unknown => {}
+        }
+    }
+
+    // fills what the book shows of a market order, returns the sweep and its value with impact
+    fn execute_market_order(
+        &mut self,
+        id: OrderId,
+        symbol: &str,
+        direction: Direction,
+        volume: Volume,
+        levels: usize,
+        traded_value: Value,
+    ) -> Result<(Sweep, Value), Error> {
+        let tick = self.market_tick(symbol, "market")?;
+        if !tick.in_trading_time() {
+            return Err(anyhow!(
+                "market order of {} at {} is not in trading time",
+                symbol, time_unparser(tick.timestamp),
+            ));
+        }
+        let bound = match tick.price_range(direction, self.price_cage) {
+            (_, high) if direction == Direction::Buy => high,
+            (low, _) => low,
+        };
+        let market = &self.markets[symbol];
+        let depth = market.book.depth(direction);
+        let sweep = market.book.quote_market_order(volume, direction, levels, bound)?;
+        let value = market.impact.cost(direction, sweep.volume, depth, sweep.value);
+        let fee = self.fee_model.fee(direction, value, traded_value);
+        if let Err(e) = self.portfolio.check(symbol, direction, sweep.volume, value + fee.total()) {
+            self.reject(direction);
+            return Err(e);
+        }
+        let market = self.markets.get_mut(symbol).unwrap();
+        market.book.take(direction, sweep.volume);
+        market.impact.record(direction, sweep.volume, depth);
+        self.push_fill(Fill {
+            order_id: id,
+            symbol: symbol.to_string(),
+            timestamp: self.now,
+            direction,
+            liquidity: Liquidity::Active,
+            price: value / sweep.volume,
+            volume: sweep.volume,
+            value,
+            fee,
+        });
+
+        Ok((sweep, value))
+    }
+
+    fn rest_limit_order(
+        &mut self,
+        id: OrderId,
+        symbol: &str,
+        direction: Direction,
+        price: Price,
+        volume: Volume,
+        traded_value: Value,
+    ) -> Result<(), Error> {
+        let tick = self.market_tick(symbol, "limit")?;
+        if let Err(e) = tick.check_price(price, direction, self.price_cage) {
+            self.reject(direction);
+            return Err(e);
+        }
+        let frozen_cash = match direction {
+            Direction::Buy => price * volume + self.fee_model.fee(direction, price * volume, traded_value).total(),
+            Direction::Sell | Direction::Unknown => 0,
+        };
+        if let Err(e) = self.portfolio.freeze(symbol, direction, volume, frozen_cash) {
+            self.reject(direction);
+            return Err(e);
+        }
+        let market = self.markets.get_mut(symbol).unwrap();
+        match tick.phase.is_auction() {
+            true => market.auction_orders.push(AuctionOrder {
+                id,
+                direction,
+                price,
+                volume,
+            }),
+            false => market.book.insert(id, direction, price, volume),
+        }
+        self.limit_orders.insert(id, LimitOrder {
+            symbol: symbol.to_string(),
+            direction,
+            price,
+            traded_value,
+            frozen_cash,
+        });
+
+        Ok(())
+    }
+
+    fn next_order_id(&mut self) -> OrderId {
+        let id = self.next_order_id;
+        self.next_order_id += 1;
+
+        id
+    }
+
+    fn push_fill(&mut self, fill: Fill) {
+        let markets = &self.markets;
+        self.portfolio.apply_fill(&fill, |symbol| markets.get(symbol).map_or(0, |market| market.last_price));
+        self.fills.push(fill);
+    }
+}
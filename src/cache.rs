@@ -0,0 +1,392 @@
+use std::collections::BTreeSet;
+use std::convert::{TryFrom, TryInto};
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Error};
+use chrono::{Datelike, NaiveDate, TimeZone};
+use memmap2::Mmap;
+
+use crate::raw_data::date_from_path;
+use crate::session::Phase;
+use crate::tick::Tick;
+use crate::transaction::{FunctionCode, OrderKind, Transaction};
+use crate::utils::{Direction, Symbol, Time, TIMEZONE};
+
+// the layout: magic, kind, rows, identity, trading date, symbol length and the symbol padded to 8 bytes,
+// then every column as `rows` little endian u64 one after another
+const MAGIC: &[u8; 8] = b"QTCACHE3";
+const HEADER: usize = 48;
+const TICKS: u64 = 0;
+const TRANSACTIONS: u64 = 1;
+const LEVELS: usize = 10;
+
+// timestamp, phase, price, ask prices and volumes, bid prices and volumes, high and low limit
+const TICK_COLUMNS: usize = 3 + 4 * LEVELS + 2;
+// timestamp, index, price, volume, direction, order kind, function code, ask order, bid order
+const TRANSACTION_COLUMNS: usize = 9;
+
+const PHASES: [Phase; 7] = [
+    Phase::PreOpen,
+    Phase::OpeningAuction,
+    Phase::Break,
+    Phase::Continuous,
+    Phase::ClosingAuction,
+    Phase::AfterHours,
+    Phase::Closed,
+];
+
+// `601012.SH.Tick.csv` is cached in `601012.SH.Tick.bin`
+pub fn cache_path(csv: &str) -> PathBuf {
+    Path::new(csv).with_extension("bin")
+}
+
+// the schema a cache was read with, as a hash
+pub fn identity(schema: &impl Debug) -> u64 {
+    // FNV-1a, unlike `DefaultHasher` the same in every build
+    format!("{:?}", schema)
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3))
+}
+
+// the trading date shared by every row, 0 if they have none or several
+fn trading_date(timestamps: &[u64]) -> Result<u64, Error> {
+    let dates = timestamps
+        .iter()
+        .map(|millis| timestamp(*millis).map(|time| time.date_naive().num_days_from_ce() as u64))
+        .collect::<Result<BTreeSet<_>, Error>>()?;
+
+    Ok(match dates.len() {
+        1 => *dates.first().unwrap(),
+        _ => 0,
+    })
+}
+
+// the identity and trading date a cache was written with
+fn written(path: &Path) -> Option<(u64, Option<NaiveDate>)> {
+    let mut header = [0; 40];
+    File::open(path).and_then(|mut file| file.read_exact(&mut header)).ok()?;
+    let word = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+    let date = i32::try_from(word(32)).ok().and_then(NaiveDate::from_num_days_from_ce_opt);
+
+    (header[..8] == MAGIC[..]).then(|| (word(24), date))
+}
+
+// whether the cache of `csv` exists, was written after it with the schema of `identity`,
+// and holds the trading date `date`, or the one in the path of `csv`, where either is known
+pub fn is_fresh(csv: &str, identity: u64, date: Option<NaiveDate>) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let newer = match (modified(&cache_path(csv)), modified(Path::new(csv))) {
+        (Some(cache), Some(csv)) => cache >= csv,
+        _ => false,
+    };
+    let expected = date.or_else(|| date_from_path(Path::new(csv)));
+
+    newer && match written(&cache_path(csv)) {
+        Some((written, date)) => written == identity && expected.is_none_or(|expected| date == Some(expected)),
+        None => false,
+    }
+}
+
+fn code(value: u64) -> Result<char, Error> {
+    u32::try_from(value).ok().and_then(char::from_u32).ok_or_else(|| anyhow!("bad code {}", value))
+}
+
+fn timestamp(millis: u64) -> Result<Time, Error> {
+    TIMEZONE
+        .timestamp_millis_opt(millis as i64)
+        .single()
+        .ok_or_else(|| anyhow!("bad timestamp {}", millis))
+}
+
+fn write(path: &Path, kind: u64, identity: u64, symbol: &str, columns: &[Vec<u64>]) -> Result<(), Error> {
+    let rows = columns.first().map_or(0, |column| column.len());
+    // written aside and renamed, a cache is never seen half written
+    let temp = path.with_extension("bin.tmp");
+    let mut writer = BufWriter::new(File::create(&temp).with_context(|| format!("create {}", temp.display()))?);
+    writer.write_all(MAGIC)?;
+    let date = trading_date(columns.first().map_or(&[][..], |column| &column[..]))?;
+    for value in [kind, rows as u64, identity, date, symbol.len() as u64] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.write_all(symbol.as_bytes())?;
+    writer.write_all(&vec![0; (8 - symbol.len() % 8) % 8])?;
+    for value in columns.iter().flatten() {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&temp, path)?;
+
+    Ok(())
+}
+
+// the rows of a file hold one symbol
+fn file_symbol(symbol: &mut Option<Symbol>, row: &str) -> Result<(), Error> {
+    match symbol {
+        Some(symbol) if symbol != row => Err(anyhow!("rows of both {} and {}", symbol, row)),
+        Some(_) => Ok(()),
+        None => {
+            *symbol = Some(row.to_string());
+            Ok(())
+        }
+    }
+}
+
+// prices or volumes of `ladder`, levels missing from the snapshot are empty
+fn levels<'a>(
+    ladder: &'a [(usize, usize)],
+    part: fn(&(usize, usize)) -> usize,
+) -> impl Iterator<Item = u64> + 'a {
+    (0..LEVELS).map(move |i| ladder.get(i).map_or(0, part) as u64)
+}
+
+// writes the ticks into the cache of `csv` under `identity`, returns the number of rows
+pub fn write_ticks(csv: &str, identity: u64, ticks: impl Iterator<Item = Result<Tick, Error>>) -> Result<usize, Error> {
+    let mut symbol = None;
+    let mut columns = vec![Vec::new(); TICK_COLUMNS];
+    for tick in ticks {
+        let tick = tick?;
+        file_symbol(&mut symbol, &tick.symbol)?;
+        let values = IntoIterator::into_iter([
+            tick.timestamp.timestamp_millis() as u64,
+            PHASES.iter().position(|phase| *phase == tick.phase).unwrap() as u64,
+            tick.new_price as u64,
+        ])
+        .chain(levels(&tick.asks, |level| level.0))
+        .chain(levels(&tick.asks, |level| level.1))
+        .chain(levels(&tick.bids, |level| level.0))
+        .chain(levels(&tick.bids, |level| level.1))
+        .chain([tick.high_limited as u64, tick.low_limited as u64]);
+        columns.iter_mut().zip(values).for_each(|(column, value)| column.push(value));
+    }
+    write(&cache_path(csv), TICKS, identity, symbol.as_deref().unwrap_or_default(), &columns)?;
+
+    Ok(columns[0].len())
+}
+
+// writes the transactions into the cache of `csv` under `identity`, returns the number of rows
+pub fn write_transactions(
+    csv: &str,
+    identity: u64,
+    transactions: impl Iterator<Item = Result<Transaction, Error>>,
+) -> Result<usize, Error> {
+    let mut symbol = None;
+    let mut columns = vec![Vec::new(); TRANSACTION_COLUMNS];
+    for transaction in transactions {
+        let transaction = transaction?;
+        file_symbol(&mut symbol, &transaction.symbol)?;
+        let values = [
+            transaction.timestamp.timestamp_millis() as u64,
+            transaction.index as u64,
+            transaction.price as u64,
+            transaction.volume as u64,
+            transaction.direction.code() as u64,
+            transaction.order_kind.code() as u64,
+            transaction.function_code.code() as u64,
+            transaction.ask_order as u64,
+            transaction.bid_order as u64,
+        ];
+        columns.iter_mut().zip(values).for_each(|(column, value)| column.push(value));
+    }
+    write(&cache_path(csv), TRANSACTIONS, identity, symbol.as_deref().unwrap_or_default(), &columns)?;
+
+    Ok(columns[0].len())
+}
+
+/// A memory-mapped cache file, values are read in place. Its rows are as the CSV was read
+/// when the cache was written, `is_fresh` tells whether that still holds.
+struct Columns {
+    mmap: Mmap,
+    rows: usize,
+    symbol: Symbol,
+    // where the first column starts
+    offset: usize,
+}
+
+impl Columns {
+    fn open(path: &Path, kind: u64, columns: usize) -> Result<Self, Error> {
+        let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+        // the cache is only ever replaced by a rename, never written in place
+        let mmap = unsafe { Mmap::map(&file)? };
+        let word = |at: usize| {
+            mmap.get(at..at + 8)
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()) as usize)
+                .ok_or_else(|| anyhow!("{} is truncated", path.display()))
+        };
+        if mmap.get(..8) != Some(&MAGIC[..]) || word(8)? as u64 != kind {
+            return Err(anyhow!("{} is not a cache of this kind", path.display()));
+        }
+        let (rows, symbol_len) = (word(16)?, word(40)?);
+        // a corrupt header must not overflow, the sizes it gives are checked against the file
+        let offset = symbol_len.checked_next_multiple_of(8).and_then(|len| len.checked_add(HEADER));
+        let len = offset.and_then(|offset| {
+            rows.checked_mul(columns)
+                .and_then(|values| values.checked_mul(8))
+                .and_then(|size| size.checked_add(offset))
+        });
+        let offset = match (offset, len) {
+            (Some(offset), Some(len)) if len == mmap.len() => offset,
+            _ => return Err(anyhow!("{} is truncated", path.display())),
+        };
+        let symbol = String::from_utf8(mmap[HEADER..HEADER + symbol_len].to_vec())?;
+
+        Ok(Self {
+            mmap,
+            rows,
+            symbol,
+            offset,
+        })
+    }
+
+    fn value(&self, column: usize, row: usize) -> u64 {
+        let at = self.offset + (column * self.rows + row) * 8;
+        u64::from_le_bytes(self.mmap[at..at + 8].try_into().unwrap())
+    }
+}
+
+/// The ticks of the cache of a tick file, one row at a time.
+pub struct CachedTicks {
+    columns: Columns,
+    row: usize,
+}
+
+impl CachedTicks {
+    pub fn open(csv: &str) -> Result<Self, Error> {
+        Ok(Self {
+            columns: Columns::open(&cache_path(csv), TICKS, TICK_COLUMNS)?,
+            row: 0,
+        })
+    }
+
+    pub fn tick(&self, row: usize) -> Result<Tick, Error> {
+        let value = |column: usize| self.columns.value(column, row) as usize;
+        let levels = |prices: usize| (0..LEVELS).map(|level| (value(prices + level), value(prices + LEVELS + level))).collect();
+
+        Ok(Tick {
+            symbol: self.columns.symbol.clone(),
+            timestamp: timestamp(value(0) as u64)?,
+            new_price: value(2),
+            asks: levels(3),
+            bids: levels(3 + 2 * LEVELS),
+            high_limited: value(3 + 4 * LEVELS),
+            low_limited: value(4 + 4 * LEVELS),
+            phase: *PHASES.get(value(1)).ok_or_else(|| anyhow!("bad phase {}", value(1)))?,
+        })
+    }
+}
+
+impl Iterator for CachedTicks {
+    type Item = Result<Tick, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.row == self.columns.rows {
+            return None;
+        }
+        self.row += 1;
+
+        Some(self.tick(self.row - 1))
+    }
+}
+
+/// The transactions of the cache of a transaction file.
+pub struct CachedTransactions {
+    columns: Columns,
+    row: usize,
+}
+
+impl CachedTransactions {
+    pub fn open(csv: &str) -> Result<Self, Error> {
+        Ok(Self {
+            columns: Columns::open(&cache_path(csv), TRANSACTIONS, TRANSACTION_COLUMNS)?,
+            row: 0,
+        })
+    }
+
+    pub fn transaction(&self, row: usize) -> Result<Transaction, Error> {
+        let value = |column: usize| self.columns.value(column, row);
+
+        Ok(Transaction {
+            symbol: self.columns.symbol.clone(),
+            timestamp: timestamp(value(0))?,
+            index: value(1) as usize,
+            price: value(2) as usize,
+            volume: value(3) as usize,
+            direction: Direction::try_from(code(value(4))?.encode_utf8(&mut [0; 4]) as &str)?,
+            order_kind: OrderKind::from_code(code(value(5))?),
+            function_code: FunctionCode::from_code(code(value(6))?),
+            ask_order: value(7) as usize,
+            bid_order: value(8) as usize,
+        })
+    }
+}
+
+impl Iterator for CachedTransactions {
+    type Item = Result<Transaction, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.row == self.columns.rows {
+            return None;
+        }
+        self.row += 1;
+
+        Some(self.transaction(self.row - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::schema::Schema;
+
+    fn tick(hour: u32, price: usize) -> Tick {
+        Tick {
+            symbol: "601012.SH".to_string(),
+            timestamp: TIMEZONE.with_ymd_and_hms(2021, 6, 4, hour, 30, 0).unwrap(),
+            new_price: price,
+            asks: vec![(price + 100, 300)],
+            bids: vec![(price - 100, 200)],
+            high_limited: 2 * price,
+            low_limited: price / 2,
+            phase: Phase::Continuous,
+        }
+    }
+
+    #[test]
+    fn cache_round_trip() {
+        let dir = std::env::temp_dir().join(format!("quant-test-cache-{}", std::process::id())).join("2021-06-04");
+        fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("601012.SH.Tick.csv");
+        let csv = csv.to_str().unwrap();
+        fs::write(csv, "").unwrap();
+        let schema = Schema::default();
+        let ticks = vec![tick(9, 500_000), tick(10, 510_000)];
+        write_ticks(csv, identity(&schema.ticks), ticks.clone().into_iter().map(Ok)).unwrap();
+
+        // the date is given, taken from the path, or another day
+        let day = NaiveDate::from_ymd_opt(2021, 6, 4);
+        assert!(is_fresh(csv, identity(&schema.ticks), day));
+        assert!(is_fresh(csv, identity(&schema.ticks), None));
+        assert!(!is_fresh(csv, identity(&schema.ticks), NaiveDate::from_ymd_opt(2021, 6, 7)));
+        let mut other = schema.ticks.clone();
+        other.price_multiplier = 100.0;
+        assert!(!is_fresh(csv, identity(&other), day));
+
+        let read = CachedTicks::open(csv).unwrap().collect::<Result<Vec<_>, Error>>().unwrap();
+        assert_eq!(read.len(), 2);
+        for (read, tick) in read.iter().zip(ticks.iter()) {
+            assert_eq!((&read.symbol, read.timestamp, read.new_price), (&tick.symbol, tick.timestamp, tick.new_price));
+            assert_eq!((&read.asks[..1], &read.bids[..1]), (&tick.asks[..], &tick.bids[..]));
+            assert_eq!((read.high_limited, read.low_limited, read.phase), (tick.high_limited, tick.low_limited, tick.phase));
+        }
+
+        let bin = cache_path(csv);
+        let bytes = fs::read(&bin).unwrap();
+        fs::write(&bin, &bytes[..bytes.len() - 8]).unwrap();
+        assert!(CachedTicks::open(csv).is_err());
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,399 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::SystemTime;
+use anyhow::{anyhow, Context, Error};
+use chrono::{NaiveDate, NaiveTime};
+use clap::{ArgGroup, Args, Parser, Subcommand};
+
+use crate::cache::{self, cache_path, identity};
+use crate::dataset::{list_days, open_day, DayFiles, SymbolFiles};
+use crate::event::{EventStream, Record};
+use crate::fee::AShareFeeModel;
+use crate::ledger;
+use crate::momentum::MomentumStrategy;
+use crate::parquet_data::{self, is_parquet};
+use crate::reader::{TickReader, TransactionReader};
+use crate::schema::{Schema, TickSchema, TransactionSchema};
+use crate::session::{Calendar, CalendarConfig};
+use crate::strategy::{StrategyConfig, StrategyContext};
+use crate::tick::Tick;
+use crate::transaction::Transaction;
+use crate::utils::{time_unparser, Direction, TimeRange};
+use crate::validation::{ValidationFailed, Validator};
+
+// data files contain unreadable rows or rows failing validation
+const EXIT_INVALID_DATA: u8 = 3;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Tick level backtest on Level-2 ticks and transactions")]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the strategy and write the ledger and summary
+    Run(RunArgs),
+    /// Check tick and transaction files row by row
+    Validate(ValidateArgs),
+    /// Print a summary of tick and transaction files
+    Inspect(InspectArgs),
+    /// Write the binary cache of tick and transaction files, `run` reads it while it is newer than the CSV
+    Convert(ConvertArgs),
+}
+
+#[derive(Debug, Args)]
+struct RunArgs {
+    /// Tick file, CSV or Parquet, repeat it together with `--transactions` to run several symbols
+    #[arg(long, default_value = "./resource/2021-06-04/601012.SH.Tick.csv")]
+    ticks: Vec<String>,
+    #[arg(long, default_value = "./resource/2021-06-04/601012.SH.Transaction.csv")]
+    transactions: Vec<String>,
+    /// Trading date of the data, by default taken from a date column or the file path
+    #[arg(long)]
+    date: Option<NaiveDate>,
+    /// Run every day in a tree like `data/{date}/{symbol}.Tick.csv` instead of the given files
+    #[arg(long, conflicts_with_all = ["ticks", "transactions", "date"])]
+    data: Option<PathBuf>,
+    /// Symbol to run from `--data` or Parquet files, repeatable, every symbol in them by default
+    #[arg(long)]
+    symbol: Vec<String>,
+    /// Only read the rows from this time of day on, e.g. `09:30:00`
+    #[arg(long)]
+    from: Option<NaiveTime>,
+    /// Only read the rows before this time of day
+    #[arg(long)]
+    to: Option<NaiveTime>,
+    #[arg(long, default_value = "./resource/strategy-config.toml")]
+    config: String,
+    /// Override a config field, e.g. `--set open_volume=2000`
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_override)]
+    overrides: Vec<(String, String)>,
+    /// Directory for ledger, summary and portfolio files
+    #[arg(long, default_value = "./output")]
+    output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct ConvertArgs {
+    /// `*.Tick.csv` and `*.Transaction.csv` files or directories holding them
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+    /// Trading date of the data, by default taken from a date column or the file path
+    #[arg(long)]
+    date: Option<NaiveDate>,
+    /// Schema of the files, `wind` or a TOML file describing it
+    #[arg(long, default_value = "wind")]
+    schema: String,
+}
+
+#[derive(Debug, Args)]
+struct ValidateArgs {
+    #[command(flatten)]
+    data: DataArgs,
+    #[arg(long, default_value = "./resource/strategy-config.toml")]
+    config: String,
+    /// Override a config field, e.g. `--set validation.crossed_book=fail`
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_override)]
+    overrides: Vec<(String, String)>,
+}
+
+#[derive(Debug, Args)]
+struct InspectArgs {
+    #[command(flatten)]
+    data: DataArgs,
+    /// Schema of the files, `wind` or a TOML file describing it
+    #[arg(long, default_value = "wind")]
+    schema: String,
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("files").required(true).multiple(true).args(["ticks", "transactions"])))]
+struct DataArgs {
+    #[arg(long)]
+    ticks: Option<String>,
+    #[arg(long)]
+    transactions: Option<String>,
+    /// Trading date of the data, by default taken from a date column or the file path
+    #[arg(long)]
+    date: Option<NaiveDate>,
+}
+
+fn parse_override(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.trim().to_string())),
+        _ => Err(format!("expected KEY=VALUE, got `{}`", s)),
+    }
+}
+
+impl Cli {
+    pub fn execute(self) -> Result<ExitCode, Error> {
+        match self.command {
+            Command::Run(args) => run(args),
+            Command::Validate(args) => validate(args),
+            Command::Inspect(args) => inspect(args),
+            Command::Convert(args) => convert(args),
+        }
+    }
+}
+
+fn run(args: RunArgs) -> Result<ExitCode, Error> {
+    let config = StrategyConfig::new_from_file(&args.config, &args.overrides)
+        .with_context(|| format!("load config {}", args.config))?;
+    let days = match &args.data {
+        Some(dir) => list_days(dir, &args.symbol)?,
+        None if args.ticks.len() != args.transactions.len() => {
+            return Err(anyhow!(
+                "{} tick files but {} transaction files, pass them in pairs",
+                args.ticks.len(), args.transactions.len(),
+            ));
+        }
+        None => {
+            let mut symbols = Vec::new();
+            for (ticks, transactions) in args.ticks.iter().zip(args.transactions.iter()) {
+                // a Parquet file may hold many symbols, each is read as one
+                let picked = match (is_parquet(ticks), args.symbol.is_empty()) {
+                    (true, true) => parquet_data::symbols(ticks, &config.schema.ticks)?.into_iter().map(Some).collect(),
+                    (true, false) => args.symbol.iter().cloned().map(Some).collect(),
+                    (false, true) => vec![None],
+                    (false, false) => return Err(anyhow!("--symbol picks symbols from --data or Parquet files")),
+                };
+                symbols.extend(picked.into_iter().map(|symbol| SymbolFiles {
+                    ticks: ticks.clone(),
+                    transactions: transactions.clone(),
+                    symbol,
+                }));
+            }
+            vec![DayFiles {
+                date: args.date,
+                symbols,
+            }]
+        }
+    };
+
+    // days are opened one at a time as the backtest reaches them and read row by row
+    let calendar = config.calendar.clone();
+    let validation = config.validation.clone();
+    let schema = config.schema.clone();
+    let hours = TimeRange {
+        from: args.from,
+        to: args.to,
+    };
+    let days = days.iter().map(|files| open_day(files, &calendar, &validation, &schema, hours));
+
+    let fee_model = Box::new(AShareFeeModel::from(&config));
+    let strategy = MomentumStrategy::new(&config);
+    let res = StrategyContext {
+        config,
+        fee_model,
+        strategy,
+    }.process(days);
+    let res = match res {
+        Err(e) if e.is::<ValidationFailed>() => {
+            println!("{}", e);
+            return Ok(ExitCode::from(EXIT_INVALID_DATA));
+        }
+        res => res?,
+    };
+    println!("{}", res);
+
+    ledger::write_result(&res, &args.output)
+        .with_context(|| format!("write result to {}", args.output.display()))?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn load_ticks(path: &str, date: Option<NaiveDate>, schema: &TickSchema, calendar: &Calendar) -> Result<Vec<Tick>, Error> {
+    let mut session = None;
+    TickReader::open(path, date, schema)?
+        .map(|tick| {
+            let mut tick = tick?;
+            tick.timestamp = calendar.timestamp(tick.timestamp)?;
+            calendar.assign_phase(&mut session, &mut tick)?;
+            Ok(tick)
+        })
+        .collect::<Result<Vec<_>, Error>>()
+        .with_context(|| format!("load ticks {}", path))
+}
+
+fn load_transactions(
+    path: &str,
+    date: Option<NaiveDate>,
+    schema: &TransactionSchema,
+    calendar: &Calendar,
+) -> Result<Vec<Transaction>, Error> {
+    TransactionReader::open(path, date, schema)?
+        .map(|transaction| {
+            let mut transaction = transaction?;
+            transaction.timestamp = calendar.timestamp(transaction.timestamp)?;
+            Ok(transaction)
+        })
+        .collect::<Result<Vec<_>, Error>>()
+        .with_context(|| format!("load transactions {}", path))
+}
+
+// returns the number of rows and prints every row that fails to read
+fn check_rows<T>(path: &str, rows: impl Iterator<Item = Result<T, Error>>) -> (usize, usize) {
+    let mut count = 0;
+    let mut errors = 0;
+    for row in rows {
+        count += 1;
+        if let Err(e) = row {
+            errors += 1;
+            println!("{}: {:#}", path, e);
+        }
+    }
+
+    (count, errors)
+}
+
+fn validate(args: ValidateArgs) -> Result<ExitCode, Error> {
+    let config = StrategyConfig::new_from_file(&args.config, &args.overrides)
+        .with_context(|| format!("load config {}", args.config))?;
+    let mut errors = 0;
+    if let Some(path) = &args.data.ticks {
+        let (rows, bad_rows) = check_rows(path, TickReader::open(path, args.data.date, &config.schema.ticks)?);
+        println!("{}: {} rows, {} unreadable", path, rows, bad_rows);
+        errors += bad_rows;
+    }
+    if let Some(path) = &args.data.transactions {
+        let (rows, bad_rows) = check_rows(path, TransactionReader::open(path, args.data.date, &config.schema.transactions)?);
+        println!("{}: {} rows, {} unreadable", path, rows, bad_rows);
+        errors += bad_rows;
+    }
+    if errors > 0 {
+        return Ok(ExitCode::from(EXIT_INVALID_DATA));
+    }
+
+    // rows are checked in time order as the backtest reads them, transactions against the snapshot in force
+    let ticks = match &args.data.ticks {
+        Some(path) => load_ticks(path, args.data.date, &config.schema.ticks, &config.calendar)?,
+        None => Vec::new(),
+    };
+    let transactions = match &args.data.transactions {
+        Some(path) => load_transactions(path, args.data.date, &config.schema.transactions, &config.calendar)?,
+        None => Vec::new(),
+    };
+    let mut validator = Validator::new(&config.validation);
+    for record in EventStream::new(vec![
+        Box::new(ticks.into_iter().map(|tick| Ok(Record::Tick(tick)))),
+        Box::new(transactions.into_iter().map(|transaction| Ok(Record::Transaction(transaction)))),
+    ]) {
+        match record? {
+            Record::Tick(tick) => validator.tick(&tick),
+            Record::Transaction(transaction) => validator.transaction(&transaction),
+        };
+    }
+    let report = validator.report();
+    report.issues.iter().for_each(|issue| println!("{}", issue));
+    println!("\n{}", report);
+
+    Ok(match report.failed() {
+        false => ExitCode::SUCCESS,
+        true => ExitCode::from(EXIT_INVALID_DATA),
+    })
+}
+
+fn inspect(args: InspectArgs) -> Result<ExitCode, Error> {
+    let schema = Schema::load(&args.schema)?;
+    // times and phases follow the default calendar
+    let calendar = Calendar::new(&CalendarConfig::default())?;
+    let args = args.data;
+    if let Some(path) = &args.ticks {
+        let ticks = load_ticks(path, args.date, &schema.ticks, &calendar)?;
+        let first = ticks.first().ok_or_else(|| anyhow!("{} has no ticks", path))?;
+        let last = ticks.last().unwrap();
+        let prices = ticks.iter().map(|tick| tick.new_price).filter(|price| *price > 0);
+        println!(
+            "[Ticks] {}\nrows: {}\ntime: {} - {}\ntrading rows: {}\nprice: {} - {}\nlast price: {}\nlimits: {} - {}\n",
+            path,
+            ticks.len(),
+            time_unparser(first.timestamp),
+            time_unparser(last.timestamp),
+            ticks.iter().filter(|tick| tick.in_trading_time()).count(),
+            prices.clone().min().unwrap_or_default(),
+            prices.max().unwrap_or_default(),
+            last.new_price,
+            last.low_limited,
+            last.high_limited,
+        );
+    }
+    if let Some(path) = &args.transactions {
+        let transactions = load_transactions(path, args.date, &schema.transactions, &calendar)?;
+        let first = transactions.first().ok_or_else(|| anyhow!("{} has no transactions", path))?;
+        let last = transactions.last().unwrap();
+        let trades = transactions.iter().filter(|transaction| !transaction.is_cancel());
+        let prices = trades.clone().map(|transaction| transaction.price);
+        let count = |direction: Direction| {
+            trades.clone().filter(|transaction| transaction.direction == direction).count()
+        };
+        let cancels = |direction: Direction| {
+            transactions
+                .iter()
+                .filter(|transaction| transaction.is_cancel() && transaction.cancel_side() == direction)
+                .count()
+        };
+        println!(
+            "[Transactions] {}\nrows: {}\ntime: {} - {}\nbuy: {}\nsell: {}\nunknown: {}\ncancelled bids: {}\ncancelled asks: {}\nvolume: {}\nprice: {} - {}\n",
+            path,
+            transactions.len(),
+            time_unparser(first.timestamp),
+            time_unparser(last.timestamp),
+            count(Direction::Buy),
+            count(Direction::Sell),
+            count(Direction::Unknown),
+            cancels(Direction::Buy),
+            cancels(Direction::Sell),
+            trades.clone().map(|transaction| transaction.volume).sum::<usize>(),
+            prices.clone().min().unwrap_or_default(),
+            prices.max().unwrap_or_default(),
+        );
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+// the tick and transaction files under `path`, or `path` itself
+fn csv_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path)
+        .with_context(|| format!("read {}", path.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for entry in entries {
+        let name = entry.file_name().unwrap_or_default().to_string_lossy();
+        if entry.is_dir() || name.ends_with(".Tick.csv") || name.ends_with(".Transaction.csv") {
+            csv_files(&entry, files)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn convert(args: ConvertArgs) -> Result<ExitCode, Error> {
+    let schema = Schema::load(&args.schema)?;
+    let (ticks, transactions) = (identity(&schema.ticks), identity(&schema.transactions));
+    let mut files = Vec::new();
+    for path in args.paths.iter() {
+        csv_files(path, &mut files)?;
+    }
+    for file in files {
+        let path = file.to_string_lossy();
+        let start = SystemTime::now();
+        let rows = match path.ends_with(".Transaction.csv") {
+            true => cache::write_transactions(&path, transactions, TransactionReader::open(&path, args.date, &schema.transactions)?),
+            false => cache::write_ticks(&path, ticks, TickReader::open(&path, args.date, &schema.ticks)?),
+        }
+        .with_context(|| format!("convert {}", path))?;
+        let elapsed = SystemTime::now().duration_since(start)?;
+        println!("{} -> {}: {} rows, {:?}", path, cache_path(&path).display(), rows, elapsed);
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
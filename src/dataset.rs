@@ -0,0 +1,235 @@
+use std::fs;
+use std::path::Path;
+use anyhow::{anyhow, Context, Error};
+use chrono::NaiveDate;
+
+use crate::cache::{identity, is_fresh, CachedTicks, CachedTransactions};
+use crate::event::{EventStream, Record, Records};
+use crate::parquet_data::{is_parquet, ParquetTicks, ParquetTransactions, RowFilter};
+use crate::raw_data::date_from_path;
+use crate::reader::{TickReader, TransactionReader};
+use crate::schema::Schema;
+use crate::session::{Calendar, TradingSession};
+use crate::tick::Tick;
+use crate::transaction::Transaction;
+use crate::utils::{Symbol, TimeRange};
+use crate::validation::{Action, ValidationConfig, ValidationFailed, Validator};
+
+/// The records of every symbol of one trading day, read as the backtest reaches them.
+pub struct Day {
+    pub date: NaiveDate,
+    pub records: Records,
+}
+
+/// The tick and transaction files of one symbol, `symbol` picks it from Parquet files holding several.
+#[derive(Debug, Clone)]
+pub struct SymbolFiles {
+    pub ticks: String,
+    pub transactions: String,
+    pub symbol: Option<Symbol>,
+}
+
+/// The data files of one trading day, `date` is `None` if it is left to the files.
+#[derive(Debug, Clone)]
+pub struct DayFiles {
+    pub date: Option<NaiveDate>,
+    pub symbols: Vec<SymbolFiles>,
+}
+
+// the symbols with a tick file in `dir`, by name
+fn symbols_in(dir: &Path) -> Result<Vec<String>, Error> {
+    let mut symbols = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("read {}", dir.display()))? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if let Some(symbol) = name.strip_suffix(".Tick.csv").or_else(|| name.strip_suffix(".Tick.parquet")) {
+            symbols.push(symbol.to_string());
+        }
+    }
+    symbols.sort();
+    symbols.dedup();
+
+    Ok(symbols)
+}
+
+// the days of `symbols` in a tree like `data/{date}/{symbol}.Tick.csv`, oldest first, every symbol
+// of the day if `symbols` is empty; CSV files are taken over Parquet ones and a symbol without
+// both files is skipped for the day
+pub fn list_days(dir: &Path, symbols: &[String]) -> Result<Vec<DayFiles>, Error> {
+    let mut days = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("read {}", dir.display()))? {
+        let path = entry?.path();
+        let date = match date_from_path(Path::new(path.file_name().unwrap_or_default())) {
+            Some(date) if path.is_dir() => date,
+            _ => continue,
+        };
+        let names = match symbols.is_empty() {
+            true => symbols_in(&path)?,
+            false => symbols.to_vec(),
+        };
+        let mut files = Vec::new();
+        for symbol in names {
+            let found = ["csv", "parquet"].iter().find_map(|extension| {
+                let ticks = path.join(format!("{}.Tick.{}", symbol, extension));
+                let transactions = path.join(format!("{}.Transaction.{}", symbol, extension));
+                Some((ticks, transactions)).filter(|(ticks, transactions)| ticks.is_file() && transactions.is_file())
+            });
+            let (ticks, transactions) = match found {
+                Some(files) => files,
+                None => {
+                    println!("skip {}: no tick or transaction file of {}", path.display(), symbol);
+                    continue;
+                }
+            };
+            files.push(SymbolFiles {
+                ticks: ticks.to_string_lossy().into_owned(),
+                transactions: transactions.to_string_lossy().into_owned(),
+                symbol: None,
+            });
+        }
+        if files.is_empty() {
+            println!("skip {}: no data", path.display());
+            continue;
+        }
+        days.push(DayFiles {
+            date: Some(date),
+            symbols: files,
+        });
+    }
+    days.sort_by_key(|day| day.date);
+
+    Ok(days)
+}
+
+// the records of one symbol in time order, rows failing validation are dropped or end the stream;
+// the report is printed once the symbol is done
+struct SymbolRecords {
+    path: String,
+    records: EventStream,
+    validator: Validator,
+    done: bool,
+}
+
+impl SymbolRecords {
+    fn print_report(&self) {
+        let report = self.validator.report();
+        if !report.issues.is_empty() {
+            println!("[{}] {}", self.path, report);
+        }
+    }
+}
+
+impl Iterator for SymbolRecords {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        for record in self.records.by_ref() {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => return Some(Err(e.context(self.path.clone()))),
+            };
+            let keep = match &record {
+                Record::Tick(tick) => self.validator.tick(tick),
+                Record::Transaction(transaction) => self.validator.transaction(transaction),
+            };
+            if self.validator.report().failed() {
+                self.done = true;
+                self.print_report();
+                self.validator.report().issues
+                    .iter()
+                    .filter(|issue| issue.action == Action::Fail)
+                    .for_each(|issue| println!("{}", issue));
+                return Some(Err(ValidationFailed(self.path.clone()).into()));
+            }
+            if keep {
+                return Some(Ok(record));
+            }
+        }
+        self.done = true;
+        self.print_report();
+
+        None
+    }
+}
+
+// opens the files of a day, from their caches where fresh; the date is that of the first tick unless given,
+// only the rows within `hours` are read
+pub fn open_day(
+    files: &DayFiles,
+    calendar: &Calendar,
+    validation: &ValidationConfig,
+    schema: &Schema,
+    hours: TimeRange,
+) -> Result<Day, Error> {
+    let mut date = files.date;
+    let mut streams: Vec<Records> = Vec::new();
+    for symbol in files.symbols.iter() {
+        let (dates, calendar) = (calendar.clone(), calendar.clone());
+        let mut session: Option<TradingSession> = None;
+        let filter = RowFilter {
+            symbol: symbol.symbol.clone(),
+            hours,
+        };
+        let ticks: Box<dyn Iterator<Item = Result<Tick, Error>>> = if is_parquet(&symbol.ticks) {
+            Box::new(ParquetTicks::open(&symbol.ticks, files.date, &schema.ticks, &filter)?)
+        } else if is_fresh(&symbol.ticks, identity(&schema.ticks), files.date) {
+            Box::new(CachedTicks::open(&symbol.ticks)?)
+        } else {
+            Box::new(TickReader::open(&symbol.ticks, files.date, &schema.ticks)?)
+        };
+        // Parquet files already skip the rows out of `hours`, CSV and cached rows are dropped here;
+        // rows are read on their trading date and the calendar moves night session rows to the evening before
+        let mut ticks = ticks
+            .filter(move |tick| tick.as_ref().map_or(true, |tick| hours.contains(tick.timestamp)))
+            .map(move |tick| -> Result<_, Error> {
+                let mut tick = tick?;
+                tick.timestamp = calendar.timestamp(tick.timestamp)?;
+                calendar.assign_phase(&mut session, &mut tick)?;
+                Ok(tick)
+            })
+            .peekable();
+        let first = match ticks.peek() {
+            Some(Ok(tick)) => dates.trading_date(tick.timestamp),
+            Some(Err(_)) => return Err(ticks.next().unwrap().unwrap_err().context(symbol.ticks.clone())),
+            None => return Err(anyhow!("{} has no ticks", symbol.ticks)),
+        };
+        match date {
+            Some(date) if files.date.is_none() && date != first => {
+                return Err(anyhow!("{} is of {}, the other files of {}", symbol.ticks, first, date));
+            }
+            _ => date = date.or(Some(first)),
+        }
+        let transactions: Box<dyn Iterator<Item = Result<Transaction, Error>>> = if is_parquet(&symbol.transactions) {
+            Box::new(ParquetTransactions::open(&symbol.transactions, files.date, &schema.transactions, &filter)?)
+        } else if is_fresh(&symbol.transactions, identity(&schema.transactions), files.date) {
+            Box::new(CachedTransactions::open(&symbol.transactions)?)
+        } else {
+            Box::new(TransactionReader::open(&symbol.transactions, files.date, &schema.transactions)?)
+        };
+        let transactions = transactions
+            .filter(move |transaction| {
+                transaction.as_ref().map_or(true, |transaction| hours.contains(transaction.timestamp))
+            })
+            .map(move |transaction| -> Result<_, Error> {
+                let mut transaction = transaction?;
+                transaction.timestamp = dates.timestamp(transaction.timestamp)?;
+                Ok(transaction)
+            });
+        streams.push(Box::new(SymbolRecords {
+            path: symbol.ticks.clone(),
+            records: EventStream::new(vec![
+                Box::new(ticks.map(|tick| tick.map(Record::Tick))),
+                Box::new(transactions.map(|transaction| transaction.map(Record::Transaction))),
+            ]),
+            validator: Validator::new(validation),
+            done: false,
+        }));
+    }
+
+    Ok(Day {
+        date: date.ok_or_else(|| anyhow!("no data files"))?,
+        records: Box::new(EventStream::new(streams)),
+    })
+}
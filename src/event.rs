@@ -0,0 +1,201 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use anyhow::Error;
+
+use crate::tick::Tick;
+use crate::transaction::Transaction;
+use crate::utils::Time;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Event<'a> {
+    Tick(&'a Tick),
+    Transaction(&'a Transaction),
+    // a cancellation record, it depletes the queue without trading
+    Cancel(&'a Transaction),
+}
+
+impl<'a> Event<'a> {
+    pub fn timestamp(&self) -> Time {
+        match self {
+            Event::Tick(tick) => tick.timestamp,
+            Event::Transaction(transaction) | Event::Cancel(transaction) => transaction.timestamp,
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        match self {
+            Event::Tick(tick) => &tick.symbol,
+            Event::Transaction(transaction) | Event::Cancel(transaction) => &transaction.symbol,
+        }
+    }
+
+    // snapshots go before transactions stamped with the same time, and those in the order of their index
+    fn sort_key(&self) -> (Time, usize, usize) {
+        match self {
+            Event::Tick(tick) => (tick.timestamp, 0, 0),
+            Event::Transaction(transaction) | Event::Cancel(transaction) => (transaction.timestamp, 1, transaction.index),
+        }
+    }
+}
+
+/// A row read from a tick or transaction file.
+#[derive(Debug)]
+pub enum Record {
+    Tick(Tick),
+    Transaction(Transaction),
+}
+
+impl Record {
+    pub fn event(&self) -> Event<'_> {
+        match self {
+            Record::Tick(tick) => Event::Tick(tick),
+            Record::Transaction(transaction) => match transaction.is_cancel() {
+                true => Event::Cancel(transaction),
+                false => Event::Transaction(transaction),
+            },
+        }
+    }
+}
+
+pub type Records = Box<dyn Iterator<Item = Result<Record, Error>>>;
+
+/// Merges time-ordered streams of records into one ordered stream, holding the records of each stream
+/// stamped with the time of its head; those are ordered by their index, e.g. the exchange sequence of
+/// transactions, and ties between streams go in the order of the streams.
+pub struct EventStream {
+    streams: Vec<Records>,
+    heads: BinaryHeap<Reverse<(Time, usize, usize, usize)>>,
+    // the records of each stream sharing the time of its head, and the first one after them
+    records: Vec<VecDeque<Record>>,
+    next: Vec<Option<Record>>,
+    error: Option<Error>,
+}
+
+impl EventStream {
+    pub fn new(streams: Vec<Records>) -> Self {
+        let mut stream = Self {
+            records: streams.iter().map(|_| VecDeque::new()).collect(),
+            next: streams.iter().map(|_| None).collect(),
+            streams,
+            heads: BinaryHeap::new(),
+            error: None,
+        };
+        (0..stream.streams.len()).for_each(|index| stream.pull(index));
+
+        stream
+    }
+
+    fn read(&mut self, index: usize) -> Option<Record> {
+        match self.next[index].take().map(Ok).or_else(|| self.streams[index].next()) {
+            Some(Ok(record)) => Some(record),
+            Some(Err(e)) => {
+                self.error = self.error.take().or(Some(e));
+                None
+            }
+            None => None,
+        }
+    }
+
+    // queues the next record of stream `index`, reading every record stamped with its time first;
+    // an error is returned before anything else
+    fn pull(&mut self, index: usize) {
+        if self.records[index].is_empty() {
+            let first = match self.read(index) {
+                Some(record) => record,
+                None => return,
+            };
+            let timestamp = first.event().timestamp();
+            self.records[index].push_back(first);
+            while let Some(record) = self.read(index) {
+                if record.event().timestamp() != timestamp {
+                    self.next[index] = Some(record);
+                    break;
+                }
+                self.records[index].push_back(record);
+            }
+            self.records[index].make_contiguous().sort_by_key(|record| record.event().sort_key());
+        }
+        if let Some(record) = self.records[index].front() {
+            let (timestamp, kind, sequence) = record.event().sort_key();
+            self.heads.push(Reverse((timestamp, kind, sequence, index)));
+        }
+    }
+}
+
+impl Iterator for EventStream {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        let Reverse((_, _, _, index)) = self.heads.pop()?;
+        let record = self.records[index].pop_front();
+        self.pull(index);
+
+        record.map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::session::Phase;
+    use crate::transaction::{FunctionCode, OrderKind};
+    use crate::utils::{Direction, TIMEZONE};
+
+    fn time(second: u32) -> Time {
+        TIMEZONE.with_ymd_and_hms(2021, 6, 4, 10, 0, second).unwrap()
+    }
+
+    fn transaction(second: u32, index: usize) -> Result<Record, Error> {
+        Ok(Record::Transaction(Transaction {
+            symbol: "601012.SH".to_string(),
+            timestamp: time(second),
+            index,
+            price: 500_000,
+            volume: 100,
+            direction: Direction::Buy,
+            order_kind: OrderKind::Unspecified,
+            function_code: FunctionCode::Trade,
+            ask_order: 0,
+            bid_order: 0,
+        }))
+    }
+
+    fn tick(second: u32) -> Result<Record, Error> {
+        Ok(Record::Tick(Tick {
+            symbol: "601012.SH".to_string(),
+            timestamp: time(second),
+            new_price: 500_000,
+            asks: Vec::new(),
+            bids: Vec::new(),
+            high_limited: 0,
+            low_limited: 0,
+            phase: Phase::Continuous,
+        }))
+    }
+
+    #[test]
+    fn ties_are_ordered_by_index() {
+        let ticks = vec![tick(0), tick(1)];
+        let transactions = vec![transaction(0, 3), transaction(1, 5), transaction(1, 4), transaction(2, 6)];
+        let order = EventStream::new(vec![Box::new(ticks.into_iter()), Box::new(transactions.into_iter())])
+            .map(|record| match record.unwrap() {
+                Record::Tick(tick) => (tick.timestamp, None),
+                Record::Transaction(transaction) => (transaction.timestamp, Some(transaction.index)),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(order, vec![
+            (time(0), None),
+            (time(0), Some(3)),
+            (time(1), None),
+            (time(1), Some(4)),
+            (time(1), Some(5)),
+            (time(2), Some(6)),
+        ]);
+    }
+}
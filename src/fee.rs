@@ -0,0 +1,95 @@
+use std::fmt::Debug;
+use std::ops::{AddAssign, SubAssign};
+use serde::Serialize;
+
+use crate::strategy::StrategyConfig;
+use crate::utils::{Direction, Value, Volume};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FeeBreakdown {
+    pub commission: Value,
+    pub stamp_duty: Value,
+    pub transfer_fee: Value,
+}
+
+impl FeeBreakdown {
+    pub fn total(&self) -> Value {
+        self.commission + self.stamp_duty + self.transfer_fee
+    }
+
+    // the share of these fees belonging to `volume` out of `total_volume`
+    pub fn split(&self, volume: Volume, total_volume: Volume) -> Self {
+        Self {
+            commission: self.commission * volume / total_volume,
+            stamp_duty: self.stamp_duty * volume / total_volume,
+            transfer_fee: self.transfer_fee * volume / total_volume,
+        }
+    }
+}
+
+impl AddAssign for FeeBreakdown {
+    fn add_assign(&mut self, other: Self) {
+        self.commission += other.commission;
+        self.stamp_duty += other.stamp_duty;
+        self.transfer_fee += other.transfer_fee;
+    }
+}
+
+impl SubAssign for FeeBreakdown {
+    fn sub_assign(&mut self, other: Self) {
+        self.commission -= other.commission;
+        self.stamp_duty -= other.stamp_duty;
+        self.transfer_fee -= other.transfer_fee;
+    }
+}
+
+pub trait FeeModel: Debug {
+    // `traded_value` is what the same order has already traded before this fill,
+    // so that per-order charges like a minimum commission are only paid once
+    fn fee(&self, direction: Direction, value: Value, traded_value: Value) -> FeeBreakdown;
+}
+
+/// Shanghai A-share fees: broker commission with a per-order minimum on both sides,
+/// stamp duty on sells and the SSE transfer fee on both sides.
+#[derive(Debug)]
+pub struct AShareFeeModel {
+    commission_ratio: f64,
+    min_commission: Value,
+    stamp_duty_ratio: f64,
+    transfer_fee_ratio: f64,
+}
+
+impl AShareFeeModel {
+    fn commission(&self, traded_value: Value) -> Value {
+        if traded_value == 0 {
+            return 0;
+        }
+        ((traded_value as f64 * self.commission_ratio).ceil() as Value).max(self.min_commission)
+    }
+}
+
+impl From<&StrategyConfig> for AShareFeeModel {
+    fn from(config: &StrategyConfig) -> Self {
+        Self {
+            commission_ratio: config.commission_ratio,
+            min_commission: config.min_commission,
+            stamp_duty_ratio: config.stamp_duty_ratio,
+            transfer_fee_ratio: config.transfer_fee_ratio,
+        }
+    }
+}
+
+impl FeeModel for AShareFeeModel {
+    fn fee(&self, direction: Direction, value: Value, traded_value: Value) -> FeeBreakdown {
+        let stamp_duty = match direction {
+            Direction::Buy | Direction::Unknown => 0,
+            Direction::Sell => (value as f64 * self.stamp_duty_ratio).ceil() as Value,
+        };
+
+        FeeBreakdown {
+            commission: self.commission(traded_value + value) - self.commission(traded_value),
+            stamp_duty,
+            transfer_fee: (value as f64 * self.transfer_fee_ratio).ceil() as Value,
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{Direction, Value, Volume};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImpactModel {
+    // market orders only pay the levels they sweep
+    None,
+    // the price moves with the square root of the order size over the displayed depth
+    SquareRoot,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImpactConfig {
+    pub model: ImpactModel,
+    // in percent, for an order as large as the displayed depth of the side it takes
+    pub temporary_percent: f64,
+    // in percent, kept for every later market order of the run
+    pub permanent_percent: f64,
+}
+
+impl Default for ImpactConfig {
+    fn default() -> Self {
+        Self {
+            model: ImpactModel::None,
+            temporary_percent: 0.1,
+            permanent_percent: 0.02,
+        }
+    }
+}
+
+/// Price impact of our market orders on top of the liquidity they sweep from the book.
+#[derive(Debug)]
+pub struct Impact {
+    model: ImpactModel,
+    temporary: f64,
+    permanent: f64,
+    // permanent impact of our orders so far, as a signed fraction of the price
+    shift: f64,
+}
+
+impl Impact {
+    pub fn new(config: &ImpactConfig) -> Self {
+        Self {
+            model: config.model,
+            temporary: config.temporary_percent / 100f64,
+            permanent: config.permanent_percent / 100f64,
+            shift: 0f64,
+        }
+    }
+
+    fn scale(&self, direction: Direction, volume: Volume, depth: Volume) -> f64 {
+        let sign = match direction {
+            Direction::Buy => 1f64,
+            Direction::Sell => -1f64,
+            Direction::Unknown => 0f64,
+        };
+        match self.model {
+            ImpactModel::None => 0f64,
+            ImpactModel::SquareRoot => sign * (volume as f64 / depth.max(1) as f64).sqrt(),
+        }
+    }
+
+    // the value of a market order of `volume` that swept `value` from a side showing `depth`
+    pub fn cost(&self, direction: Direction, volume: Volume, depth: Volume, value: Value) -> Value {
+        let shift = self.shift + self.temporary * self.scale(direction, volume, depth);
+
+        (value as f64 * (1f64 + shift)).max(0f64).round() as Value
+    }
+
+    // moves the price for later orders once the order has traded
+    pub fn record(&mut self, direction: Direction, volume: Volume, depth: Volume) {
+        self.shift += self.permanent * self.scale(direction, volume, depth);
+    }
+}
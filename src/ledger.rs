@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use anyhow::Error;
+use serde::Serialize;
+
+use crate::broker::{Fill, Liquidity, OrderId};
+use crate::strategy::StrategyResult;
+use crate::utils::{serialize_time, Direction, Pnl, Price, Symbol, Time, Value, Volume};
+
+/// An opening buy together with every fill that closed it.
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundTrip {
+    pub open_order_id: OrderId,
+    pub symbol: Symbol,
+    #[serde(serialize_with = "serialize_time")]
+    pub entry_time: Time,
+    pub entry_price: Price,
+    pub volume: Volume,
+    pub entry_value: Value,
+    pub entry_fee: Value,
+    #[serde(serialize_with = "serialize_time")]
+    pub exit_time: Time,
+    pub exit_price: Price,
+    pub closed_volume: Volume,
+    pub active_close_volume: Volume,
+    pub passive_close_volume: Volume,
+    pub exit_value: Value,
+    pub exit_fee: Value,
+    pub pnl: Pnl,
+    #[serde(skip)]
+    pub closes: Vec<Fill>,
+}
+
+impl RoundTrip {
+    fn open(fill: &Fill) -> Self {
+        Self {
+            open_order_id: fill.order_id,
+            symbol: fill.symbol.clone(),
+            entry_time: fill.timestamp,
+            entry_price: fill.price,
+            volume: fill.volume,
+            entry_value: fill.value,
+            entry_fee: fill.fee.total(),
+            // the entry time until the first close
+            exit_time: fill.timestamp,
+            exit_price: 0,
+            closed_volume: 0,
+            active_close_volume: 0,
+            passive_close_volume: 0,
+            exit_value: 0,
+            exit_fee: 0,
+            pnl: 0,
+            closes: Vec::new(),
+        }
+    }
+
+    fn open_volume(&self) -> Volume {
+        self.volume - self.closed_volume
+    }
+
+    fn close(&mut self, fill: &Fill) {
+        self.exit_time = fill.timestamp;
+        self.closed_volume += fill.volume;
+        match fill.liquidity {
+            Liquidity::Active => self.active_close_volume += fill.volume,
+            Liquidity::Passive => self.passive_close_volume += fill.volume,
+        }
+        self.exit_value += fill.value;
+        self.exit_fee += fill.fee.total();
+        self.exit_price = self.exit_value / self.closed_volume;
+        let entry_cost = (self.entry_value + self.entry_fee) * self.closed_volume / self.volume;
+        self.pnl = self.exit_value as Pnl - self.exit_fee as Pnl - entry_cost as Pnl;
+        self.closes.push(fill.clone());
+    }
+
+    // splits `fill` so that only what is still open gets closed, returns the rest
+    fn close_partially(&mut self, fill: &Fill) -> Option<Fill> {
+        if self.open_volume() == 0 {
+            return Some(fill.clone());
+        }
+        let volume = fill.volume.min(self.open_volume());
+        if volume == fill.volume {
+            self.close(fill);
+            return None;
+        }
+        let mut part = fill.clone();
+        part.volume = volume;
+        part.value = fill.price * volume;
+        part.fee = fill.fee.split(volume, fill.volume);
+        self.close(&part);
+
+        let mut rest = fill.clone();
+        rest.volume -= volume;
+        rest.value -= part.value;
+        rest.fee -= part.fee;
+        Some(rest)
+    }
+}
+
+// sells linked to an opening order close that round trip, the others close open round trips
+// of their symbol first in first out
+pub fn build_round_trips(fills: &[Fill], links: &HashMap<OrderId, OrderId>) -> Vec<RoundTrip> {
+    let mut round_trips: Vec<RoundTrip> = Vec::new();
+    let mut index_by_order: HashMap<OrderId, usize> = HashMap::new();
+    for fill in fills {
+        match fill.direction {
+            Direction::Buy => match index_by_order.get(&fill.order_id) {
+                Some(&index) => {
+                    let round_trip = &mut round_trips[index];
+                    round_trip.volume += fill.volume;
+                    round_trip.entry_value += fill.value;
+                    round_trip.entry_fee += fill.fee.total();
+                    round_trip.entry_price = round_trip.entry_value / round_trip.volume;
+                }
+                None => {
+                    index_by_order.insert(fill.order_id, round_trips.len());
+                    round_trips.push(RoundTrip::open(fill));
+                }
+            },
+            Direction::Sell => {
+                let mut rest = Some(fill.clone());
+                if let Some(index) = links.get(&fill.order_id).and_then(|id| index_by_order.get(id)) {
+                    rest = round_trips[*index].close_partially(fill);
+                }
+                let open = round_trips
+                    .iter_mut()
+                    .filter(|round_trip| round_trip.symbol == fill.symbol && round_trip.open_volume() > 0);
+                for round_trip in open {
+                    rest = match rest {
+                        Some(fill) => round_trip.close_partially(&fill),
+                        None => break,
+                    };
+                }
+            }
+            Direction::Unknown => {}
+        }
+    }
+
+    round_trips
+}
+
+#[derive(Debug, Serialize)]
+struct Summary {
+    days: usize,
+    open_times: usize,
+    open_value: Value,
+    close_active_traded_times: usize,
+    close_active_traded_value: Pnl,
+    close_passive_traded_times: usize,
+    close_passive_traded_value: Pnl,
+    yield_rate: f64,
+    commission: Value,
+    stamp_duty: Value,
+    transfer_fee: Value,
+    rejected_buy_times: usize,
+    rejected_sell_times: usize,
+    blocked_open_times: usize,
+    blocked_close_times: usize,
+    position: Volume,
+    unsettled_position: Volume,
+    cash: Pnl,
+    realized_pnl: Pnl,
+    unrealized_pnl: Pnl,
+}
+
+impl From<&StrategyResult> for Summary {
+    fn from(res: &StrategyResult) -> Self {
+        Self {
+            days: res.daily.len(),
+            open_times: res.open_times,
+            open_value: res.open_value,
+            close_active_traded_times: res.close_active_traded_times,
+            close_active_traded_value: res.close_active_traded_value,
+            close_passive_traded_times: res.close_passive_traded_times,
+            close_passive_traded_value: res.close_passive_traded_value,
+            yield_rate: res.yield_rate,
+            commission: res.fee.commission,
+            stamp_duty: res.fee.stamp_duty,
+            transfer_fee: res.fee.transfer_fee,
+            rejected_buy_times: res.rejected_buy_times,
+            rejected_sell_times: res.rejected_sell_times,
+            blocked_open_times: res.blocked_open_times,
+            blocked_close_times: res.blocked_close_times,
+            position: res.position,
+            unsettled_position: res.unsettled_position,
+            cash: res.cash,
+            realized_pnl: res.realized_pnl,
+            unrealized_pnl: res.unrealized_pnl,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RoundTripWithFills<'a> {
+    #[serde(flatten)]
+    round_trip: &'a RoundTrip,
+    closes: &'a [Fill],
+}
+
+fn write_csv<T: Serialize>(path: &Path, records: impl IntoIterator<Item = T>) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+// writes ledger.csv, ledger.json, summary.csv, summary.json, portfolio.csv, daily.csv and symbols.csv into `dir`
+pub fn write_result(res: &StrategyResult, dir: &Path) -> Result<(), Error> {
+    fs::create_dir_all(dir)?;
+    let summary = Summary::from(res);
+
+    write_csv(&dir.join("ledger.csv"), &res.round_trips)?;
+    let round_trips = res.round_trips
+        .iter()
+        .map(|round_trip| RoundTripWithFills {
+            round_trip,
+            closes: &round_trip.closes,
+        })
+        .collect::<Vec<_>>();
+    fs::write(dir.join("ledger.json"), serde_json::to_string_pretty(&round_trips)?)?;
+
+    write_csv(&dir.join("summary.csv"), Some(&summary))?;
+    fs::write(dir.join("summary.json"), serde_json::to_string_pretty(&summary)?)?;
+
+    write_csv(&dir.join("portfolio.csv"), &res.portfolio_history)?;
+    write_csv(&dir.join("daily.csv"), &res.daily)?;
+    write_csv(&dir.join("symbols.csv"), &res.symbols)?;
+
+    Ok(())
+}
@@ -0,0 +1,23 @@
+pub mod tick;
+pub mod transaction;
+pub mod raw_data;
+pub mod strategy;
+pub mod broker;
+pub mod cache;
+pub mod dataset;
+pub mod event;
+pub mod fee;
+pub mod impact;
+pub mod ledger;
+pub mod momentum;
+pub mod order_book;
+pub mod parquet_data;
+pub mod portfolio;
+pub mod queue;
+pub mod reader;
+pub mod schema;
+pub mod session;
+pub mod settlement;
+pub mod validation;
+pub mod cli;
+pub mod utils;
@@ -0,0 +1,210 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::broker::{Broker, Fill, OrderId, OrderType, Remainder, TimerToken};
+use crate::strategy::{Strategy, StrategyConfig};
+use crate::tick::Tick;
+use chrono::TimeDelta;
+
+use crate::utils::{time_unparser, Direction, Price, Symbol, Time, Volume};
+
+#[derive(Debug)]
+enum CloseStage {
+    // bought, waiting to place the limit close
+    Opened(Volume),
+    // limit close resting at ask1
+    Pending(OrderId),
+    // limit close expired, selling the rest by market
+    Flattening(Volume),
+    // bought today under T+1, closed from the next day on
+    Unsettled(Volume),
+}
+
+// the recent prices of one symbol
+#[derive(Debug, Default)]
+struct Signal {
+    history: VecDeque<(Time, Price)>,
+    last_open: Option<Time>,
+}
+
+/// Buys `open_volume` by market after a `rise_threshold` move within `rise_duration`,
+/// closes each position with a limit order at ask1 after `limit_close_elapsed`
+/// and sells whatever is left by market after another `close_waiting_elapsed`, each symbol on its own.
+#[derive(Debug)]
+pub struct MomentumStrategy {
+    rise_duration: TimeDelta,
+    rise_threshold: f64,
+    open_volume: Volume,
+    open_min_interval: TimeDelta,
+    // market order type of the opens, the closes sweep the book
+    open_order_type: OrderType,
+    limit_close_elapsed: TimeDelta,
+    close_waiting_elapsed: TimeDelta,
+    signals: HashMap<Symbol, Signal>,
+    positions: HashMap<TimerToken, (Symbol, CloseStage)>,
+}
+
+impl MomentumStrategy {
+    pub fn new(config: &StrategyConfig) -> Self {
+        Self {
+            rise_duration: config.rise_duration,
+            rise_threshold: config.rise_threshold,
+            open_volume: config.open_volume,
+            open_min_interval: config.open_min_interval,
+            open_order_type: config.open_order_type,
+            limit_close_elapsed: config.limit_close_elapsed,
+            close_waiting_elapsed: config.close_waiting_elapsed,
+            signals: HashMap::new(),
+            positions: HashMap::new(),
+        }
+    }
+
+    fn open_trigger(&self, signal: &Signal, open_tick: &Tick) -> bool {
+        let too_soon = signal.last_open.is_some_and(|last| open_tick.timestamp - last <= self.open_min_interval);
+        if !open_tick.in_trading_time() || too_soon {
+            return false;
+        }
+        let expect_price = (open_tick.new_price as f64) * (1f64 + self.rise_threshold);
+        signal.history
+            .iter()
+            .any(|(_, price)| *price as f64 >= expect_price)
+    }
+
+    fn sell_by_market(&mut self, broker: &mut Broker, token: TimerToken, symbol: Symbol, volume: Volume) {
+        // auctions take no market orders, a sell at limit-down fills at the uncrossing price
+        let auction_price = broker.tick(&symbol)
+            .filter(|_| broker.phase(&symbol).is_auction())
+            .and_then(|tick| tick.limit_price(Direction::Sell));
+        if let Some(price) = auction_price {
+            match broker.submit_limit_order(&symbol, Direction::Sell, price, volume) {
+                Ok(order_id) => {
+                    broker.link_close(order_id, token);
+                    self.positions.remove(&token);
+                }
+                Err(e) => println!("error: {:?}, volume: {}", e, volume),
+            }
+            return;
+        }
+        match broker.submit_market_order(&symbol, OrderType::FullDepth, Direction::Sell, volume) {
+            Ok(order) => {
+                broker.link_close(order.id, token);
+                match order.remainder {
+                    // sell what the book could not take on the next tick
+                    Remainder::Cancelled => {
+                        self.positions.insert(token, (symbol, CloseStage::Flattening(order.unfilled_volume)));
+                        broker.set_timer(broker.now(), token);
+                    }
+                    _ => {
+                        self.positions.remove(&token);
+                    }
+                }
+            }
+            Err(e) => {
+                println!("{:?}", e);
+                // retry on the next tick
+                self.positions.insert(token, (symbol, CloseStage::Flattening(volume)));
+                broker.set_timer(broker.now(), token);
+            }
+        }
+    }
+}
+
+impl Strategy for MomentumStrategy {
+    fn on_tick(&mut self, broker: &mut Broker, tick: &Tick) {
+        let mut signal = self.signals.remove(&tick.symbol).unwrap_or_default();
+        while let Some((timestamp, _)) = signal.history.front() {
+            if tick.timestamp - timestamp > self.rise_duration {
+                signal.history.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if self.open_trigger(&signal, tick) {
+            let volume = self.open_volume;
+            match broker.submit_market_order(&tick.symbol, self.open_order_type, Direction::Buy, volume) {
+                Ok(order) => {
+                    if order.unfilled_volume > 0 {
+                        println!("open filled {} of {} at {}", order.filled_volume, volume, order.average_price);
+                    }
+                    signal.last_open = Some(tick.timestamp);
+                }
+                Err(e) => println!("error: {:?}, volume: {}", e, volume),
+            }
+        }
+        signal.history.push_back((tick.timestamp, tick.new_price));
+        self.signals.insert(tick.symbol.clone(), signal);
+    }
+
+    fn on_fill(&mut self, broker: &mut Broker, fill: &Fill) {
+        if fill.direction == Direction::Buy {
+            // later fills of the same open join the position before it is closed
+            let token = fill.order_id;
+            match self.positions.get_mut(&token) {
+                Some((_, CloseStage::Opened(volume))) => *volume += fill.volume,
+                Some(_) => println!("fill of order {} after its close started", token),
+                None => {
+                    self.positions.insert(token, (fill.symbol.clone(), CloseStage::Opened(fill.volume)));
+                    broker.set_timer_after(self.limit_close_elapsed, token);
+                }
+            }
+        }
+    }
+
+    fn on_timer(&mut self, broker: &mut Broker, token: TimerToken) {
+        let (symbol, stage) = match self.positions.remove(&token) {
+            Some(position) => position,
+            None => return,
+        };
+        match stage {
+            CloseStage::Opened(volume) => {
+                // whatever of the open still rests in the book is given up
+                broker.cancel_order(token);
+                if broker.portfolio().available_volume(&symbol) < volume {
+                    self.positions.insert(token, (symbol, CloseStage::Unsettled(volume)));
+                    return;
+                }
+                let price = match broker.tick(&symbol).and_then(|tick| tick.get_first_ask_price()) {
+                    Some(price) => price,
+                    None => {
+                        println!("ask plate of {} is empty at {}", symbol, time_unparser(broker.now()));
+                        self.positions.insert(token, (symbol, CloseStage::Opened(volume)));
+                        broker.set_timer(broker.now(), token);
+                        return;
+                    }
+                };
+                match broker.submit_limit_order(&symbol, Direction::Sell, price, volume) {
+                    Ok(order_id) => {
+                        broker.link_close(order_id, token);
+                        self.positions.insert(token, (symbol, CloseStage::Pending(order_id)));
+                        broker.set_timer_after(self.close_waiting_elapsed, token);
+                    }
+                    Err(e) => println!("error: {:?}, volume: {}", e, volume),
+                }
+            }
+            CloseStage::Pending(order_id) => {
+                if let Some(volume) = broker.cancel_order(order_id) {
+                    self.sell_by_market(broker, token, symbol, volume);
+                }
+            }
+            CloseStage::Flattening(volume) => self.sell_by_market(broker, token, symbol, volume),
+            stage @ CloseStage::Unsettled(_) => {
+                self.positions.insert(token, (symbol, stage));
+            }
+        }
+    }
+
+    // closes still resting at the close and positions waiting for settlement start over on the next day
+    fn on_day_end(&mut self, broker: &mut Broker) {
+        for (token, (_, stage)) in self.positions.iter_mut() {
+            let volume = match *stage {
+                CloseStage::Pending(order_id) => broker.cancel_order(order_id),
+                CloseStage::Unsettled(volume) => Some(volume),
+                _ => None,
+            };
+            if let Some(volume) = volume {
+                *stage = CloseStage::Opened(volume);
+                broker.set_timer(broker.now(), *token);
+            }
+        }
+    }
+}
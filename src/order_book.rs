@@ -0,0 +1,384 @@
+use anyhow::{anyhow, Error};
+
+use crate::broker::OrderId;
+use crate::queue::QueueModel;
+use crate::tick::Tick;
+use crate::transaction::Transaction;
+use crate::utils::{time_origin, time_unparser, Direction, Price, Time, Value, Volume};
+
+#[derive(Debug)]
+struct RestingOrder {
+    id: OrderId,
+    direction: Direction,
+    price: Price,
+    volume: Volume,
+    // volume of others in front of us at our price
+    ahead: Volume,
+}
+
+// whether `price` is further from the top of the `side` ladder than `other`
+fn behind(side: Direction, price: Price, other: Price) -> bool {
+    match side {
+        Direction::Buy => price < other,
+        Direction::Sell | Direction::Unknown => price > other,
+    }
+}
+
+fn load(levels: &mut Vec<(Price, Volume)>, ladder: &[(Price, Volume)]) {
+    levels.clear();
+    levels.extend(ladder.iter().filter(|(price, _)| *price > 0).copied());
+}
+
+fn reduce(levels: &mut Vec<(Price, Volume)>, price: Price, volume: Volume) {
+    if let Some(index) = levels.iter().position(|(p, _)| *p == price) {
+        levels[index].1 = levels[index].1.saturating_sub(volume);
+        if levels[index].1 == 0 {
+            levels.remove(index);
+        }
+    }
+}
+
+/// The part of a market order the book can fill.
+#[derive(Debug, Default)]
+pub struct Sweep {
+    pub volume: Volume,
+    pub value: Value,
+    // price of the deepest level reached
+    pub last_price: Price,
+}
+
+/// The book of the last snapshot worked down by every transaction since, with our resting orders in it.
+#[derive(Debug)]
+pub struct OrderBook {
+    queue_model: QueueModel,
+    timestamp: Time,
+    // best level first, ours excluded
+    asks: Vec<(Price, Volume)>,
+    bids: Vec<(Price, Volume)>,
+    // the snapshot showed every level of the side
+    asks_complete: bool,
+    bids_complete: bool,
+    orders: Vec<RestingOrder>,
+}
+
+impl OrderBook {
+    pub fn new(queue_model: QueueModel) -> Self {
+        Self {
+            queue_model,
+            timestamp: time_origin(),
+            asks: Vec::new(),
+            bids: Vec::new(),
+            asks_complete: false,
+            bids_complete: false,
+            orders: Vec::new(),
+        }
+    }
+
+    fn levels_mut(&mut self, side: Direction) -> Option<&mut Vec<(Price, Volume)>> {
+        match side {
+            Direction::Buy => Some(&mut self.bids),
+            Direction::Sell => Some(&mut self.asks),
+            Direction::Unknown => None,
+        }
+    }
+
+    // volume resting at `price` on `side`, ours excluded,
+    // `None` if the price may lie beyond the displayed depth
+    pub fn volume_at(&self, price: Price, side: Direction) -> Option<Volume> {
+        let (levels, complete) = match side {
+            Direction::Buy => (&self.bids, self.bids_complete),
+            Direction::Sell => (&self.asks, self.asks_complete),
+            Direction::Unknown => return None,
+        };
+        for (p, v) in levels.iter() {
+            if *p == price {
+                return Some(*v);
+            }
+            if behind(side, *p, price) {
+                return Some(0);
+            }
+        }
+
+        match complete {
+            true => Some(0),
+            false => None,
+        }
+    }
+
+    pub fn reset(&mut self, tick: &Tick) {
+        let expected = self.orders
+            .iter()
+            .map(|order| self.volume_at(order.price, order.direction))
+            .collect::<Vec<_>>();
+        self.timestamp = tick.timestamp;
+        load(&mut self.asks, &tick.asks);
+        load(&mut self.bids, &tick.bids);
+        self.asks_complete = self.asks.len() < tick.asks.len();
+        self.bids_complete = self.bids.len() < tick.bids.len();
+
+        let model = self.queue_model;
+        for (index, expected) in expected.into_iter().enumerate() {
+            let displayed = self.volume_at(self.orders[index].price, self.orders[index].direction);
+            model.refresh(&mut self.orders[index].ahead, expected, displayed);
+        }
+    }
+
+    pub fn insert(&mut self, id: OrderId, direction: Direction, price: Price, volume: Volume) {
+        // our earlier orders at the price keep their priority through the order of `orders`
+        let level = self.volume_at(price, direction).unwrap_or_default();
+        self.orders.push(RestingOrder {
+            id,
+            direction,
+            price,
+            volume,
+            ahead: self.queue_model.join(level),
+        });
+    }
+
+    // takes our order out of the book and returns its unfilled volume
+    pub fn remove(&mut self, id: OrderId) -> Option<Volume> {
+        let index = self.orders.iter().position(|order| order.id == id)?;
+
+        Some(self.orders.remove(index).volume)
+    }
+
+    // works a trade down the book and returns `(order, filled, unfilled)` for each of our orders it fills
+    pub fn apply_trade(&mut self, transaction: &Transaction) -> Vec<(OrderId, Volume, Volume)> {
+        let model = self.queue_model;
+        let mut fills = Vec::new();
+        for side in [Direction::Buy, Direction::Sell] {
+            if transaction.aggressor(side) != side.opposite() {
+                continue;
+            }
+            let level = self.volume_at(transaction.price, side).unwrap_or_default();
+            // a trade through our price would have taken our orders first, the best priced first,
+            // and ours at the price in the order we placed them
+            let mut queue = (0..self.orders.len())
+                .filter(|index| {
+                    let order = &self.orders[*index];
+                    order.direction == side && !behind(side, order.price, transaction.price)
+                })
+                .collect::<Vec<_>>();
+            queue.sort_by(|a, b| {
+                let (a, b) = (self.orders[*a].price, self.orders[*b].price);
+                match side {
+                    Direction::Buy => b.cmp(&a),
+                    Direction::Sell | Direction::Unknown => a.cmp(&b),
+                }
+            });
+            // what the trade has left for the next of our orders
+            let mut remaining = transaction.volume;
+            for index in queue {
+                if remaining == 0 {
+                    break;
+                }
+                let order = &mut self.orders[index];
+                let volume = if order.price == transaction.price {
+                    model.trade(&mut order.ahead, level, remaining, order.volume)
+                } else {
+                    order.ahead = 0;
+                    order.volume.min(remaining)
+                };
+                if volume > 0 {
+                    remaining -= volume;
+                    order.volume -= volume;
+                    fills.push((order.id, volume, order.volume));
+                }
+            }
+
+            // levels before the trade price are gone
+            let levels = self.levels_mut(side).unwrap();
+            let gone = levels.iter().take_while(|(p, _)| behind(side, transaction.price, *p)).count();
+            levels.drain(..gone);
+            reduce(levels, transaction.price, remaining);
+        }
+        self.orders.retain(|order| order.volume > 0);
+
+        fills
+    }
+
+    // a cancellation at a known price takes volume off its level, evenly around our orders there
+    pub fn apply_cancel(&mut self, transaction: &Transaction) {
+        let side = transaction.cancel_side();
+        let level = match self.volume_at(transaction.price, side) {
+            Some(level) if transaction.price > 0 => level,
+            _ => return,
+        };
+        let model = self.queue_model;
+        self.orders
+            .iter_mut()
+            .filter(|order| order.direction == side && order.price == transaction.price)
+            .for_each(|order| model.cancel(&mut order.ahead, level, transaction.volume));
+        reduce(self.levels_mut(side).unwrap(), transaction.price, transaction.volume);
+    }
+
+    // the levels a market order of `direction` takes from
+    fn opposite_levels(&self, direction: Direction) -> Result<&[(Price, Volume)], Error> {
+        match direction {
+            Direction::Buy => Ok(&self.asks),
+            Direction::Sell => Ok(&self.bids),
+            Direction::Unknown => Err(anyhow!("market order without direction")),
+        }
+    }
+
+    // the best price resting on `side`
+    pub fn best_price(&self, side: Direction) -> Option<Price> {
+        match side {
+            Direction::Buy => self.bids.first().map(|(price, _)| *price),
+            Direction::Sell => self.asks.first().map(|(price, _)| *price),
+            Direction::Unknown => None,
+        }
+    }
+
+    // volume a market order of `direction` could take
+    pub fn depth(&self, direction: Direction) -> Volume {
+        self.opposite_levels(direction)
+            .map(|levels| levels.iter().map(|(_, volume)| volume).sum())
+            .unwrap_or_default()
+    }
+
+    // what a market order of `direction` would take from the best `levels` levels priced up to `bound`
+    pub fn quote_market_order(
+        &self,
+        volume: Volume,
+        direction: Direction,
+        levels: usize,
+        bound: Price,
+    ) -> Result<Sweep, Error> {
+        assert_ne!(volume, 0, "volume of market order should not be zero");
+        let mut sweep = Sweep::default();
+        let within = |price: &Price| !behind(direction.opposite(), *price, bound);
+        for (p, v) in self.opposite_levels(direction)?.iter().take(levels).take_while(|(p, _)| within(p)) {
+            let taken = (*v).min(volume - sweep.volume);
+            sweep.volume += taken;
+            sweep.value += p * taken;
+            sweep.last_price = *p;
+            if sweep.volume == volume {
+                break;
+            }
+        }
+
+        if sweep.volume == 0 {
+            return Err(anyhow!(
+                "market order at {} finds no liquidity up to {}",
+                time_unparser(self.timestamp), bound,
+            ));
+        }
+
+        Ok(sweep)
+    }
+
+    // removes what a market order of ours took, until the next snapshot
+    pub fn take(&mut self, direction: Direction, volume: Volume) {
+        let levels = match self.levels_mut(direction.opposite()) {
+            Some(levels) => levels,
+            None => return,
+        };
+        let mut left_volume = volume;
+        for (_, v) in levels.iter_mut() {
+            let taken = left_volume.min(*v);
+            *v -= taken;
+            left_volume -= taken;
+            if left_volume == 0 {
+                break;
+            }
+        }
+        levels.retain(|(_, v)| *v > 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::session::Phase;
+    use crate::transaction::{FunctionCode, OrderKind};
+    use crate::utils::TIMEZONE;
+
+    fn book(model: QueueModel, bids: &[(Price, Volume)]) -> OrderBook {
+        let mut book = OrderBook::new(model);
+        book.reset(&snapshot(bids));
+        book
+    }
+
+    fn snapshot(bids: &[(Price, Volume)]) -> Tick {
+        Tick {
+            symbol: "601012.SH".to_string(),
+            timestamp: TIMEZONE.with_ymd_and_hms(2021, 6, 4, 10, 0, 0).unwrap(),
+            new_price: bids[0].0,
+            asks: vec![(bids[0].0 + 1, 1000)],
+            bids: bids.to_vec(),
+            high_limited: 2 * bids[0].0,
+            low_limited: 0,
+            phase: Phase::Continuous,
+        }
+    }
+
+    // a sell taking from the bids
+    fn sell(price: Price, volume: Volume) -> Transaction {
+        Transaction {
+            symbol: "601012.SH".to_string(),
+            timestamp: TIMEZONE.with_ymd_and_hms(2021, 6, 4, 10, 0, 1).unwrap(),
+            index: 1,
+            price,
+            volume,
+            direction: Direction::Sell,
+            order_kind: OrderKind::Unspecified,
+            function_code: FunctionCode::Trade,
+            ask_order: 0,
+            bid_order: 0,
+        }
+    }
+
+    #[test]
+    fn fifo_queue_is_shared_by_our_orders() {
+        let mut book = book(QueueModel::Fifo, &[(100, 500)]);
+        book.insert(1, Direction::Buy, 100, 100);
+        book.insert(2, Direction::Buy, 100, 100);
+        // both wait behind the same 500, the first of ours takes what is left of the trade
+        assert_eq!(book.apply_trade(&sell(100, 550)), vec![(1, 50, 50)]);
+        assert_eq!((book.orders[0].ahead, book.orders[1].ahead), (0, 0));
+        assert_eq!(book.apply_trade(&sell(100, 100)), vec![(1, 50, 0), (2, 50, 50)]);
+        assert_eq!(book.orders.len(), 1);
+    }
+
+    #[test]
+    fn pro_rata_shares_trades_with_the_level() {
+        let mut book = book(QueueModel::ProRata, &[(100, 300)]);
+        book.insert(1, Direction::Buy, 100, 100);
+        assert_eq!(book.orders[0].ahead, 0);
+        // a quarter of the level is ours
+        assert_eq!(book.apply_trade(&sell(100, 200)), vec![(1, 50, 50)]);
+        assert_eq!(book.volume_at(100, Direction::Buy), Some(150));
+    }
+
+    #[test]
+    fn trade_through_our_price_drains_better_levels() {
+        let mut book = book(QueueModel::Fifo, &[(101, 200), (100, 300), (99, 400)]);
+        book.insert(1, Direction::Buy, 100, 100);
+        book.insert(2, Direction::Buy, 101, 100);
+        // the best priced of ours fills first, the queue ahead of them no longer matters
+        assert_eq!(book.apply_trade(&sell(99, 150)), vec![(2, 100, 0), (1, 50, 50)]);
+        assert_eq!(book.orders[0].ahead, 0);
+        assert_eq!(book.best_price(Direction::Buy), Some(99));
+        assert_eq!(book.volume_at(99, Direction::Buy), Some(400));
+        assert_eq!(book.volume_at(100, Direction::Buy), Some(0));
+    }
+
+    #[test]
+    fn refresh_takes_cancels_from_the_queue() {
+        let mut book = book(QueueModel::Fifo, &[(100, 500)]);
+        book.insert(1, Direction::Buy, 100, 100);
+        // volume joining behind us leaves our place
+        book.reset(&snapshot(&[(100, 600)]));
+        assert_eq!(book.orders[0].ahead, 500);
+        // half the level was cancelled, evenly ahead of and behind us
+        book.reset(&snapshot(&[(100, 300)]));
+        assert_eq!(book.orders[0].ahead, 250);
+        // a level beyond the displayed depth tells nothing
+        book.reset(&snapshot(&[(102, 100)]));
+        assert_eq!(book.orders[0].ahead, 250);
+        assert_eq!(book.apply_trade(&sell(100, 260)), vec![(1, 10, 90)]);
+    }
+}
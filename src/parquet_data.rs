@@ -0,0 +1,590 @@
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::str;
+use anyhow::{anyhow, Context, Error};
+use chrono::{NaiveDate, NaiveTime, TimeDelta, TimeZone, Timelike};
+use parquet::basic::{ConvertedType, LogicalType, TimeUnit};
+use parquet::file::metadata::{ColumnChunkMetaData, RowGroupMetaData};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::serialized_reader::ReadOptionsBuilder;
+use parquet::file::statistics::Statistics;
+use parquet::record::reader::RowIter;
+use parquet::record::Field;
+use parquet::schema::types::Type;
+
+use crate::raw_data::TradingDate;
+use crate::schema::{level_column, TickSchema, TimeFormat, TransactionSchema, Value};
+use crate::session::Phase;
+use crate::tick::Tick;
+use crate::transaction::{FunctionCode, OrderKind, Transaction};
+use crate::utils::{Symbol, Time, TimeRange, TIMEZONE};
+
+pub fn is_parquet(path: &str) -> bool {
+    path.ends_with(".parquet")
+}
+
+/// The rows to read from a file: those of `symbol`, every symbol if `None`, within `hours`.
+#[derive(Debug, Clone, Default)]
+pub struct RowFilter {
+    pub symbol: Option<Symbol>,
+    pub hours: TimeRange,
+}
+
+// `time` as HHMMSSmmm
+fn n_time(time: NaiveTime) -> i64 {
+    ((time.hour() * 100 + time.minute()) * 100 + time.second()) as i64 * 1000 + (time.nanosecond() / 1_000_000) as i64
+}
+
+// the range of a column in a row group as integers, `None` without statistics
+fn int_range(statistics: &Statistics) -> Option<(i64, i64)> {
+    match statistics {
+        Statistics::Int32(s) => Some((*s.min_opt()? as i64, *s.max_opt()? as i64)),
+        Statistics::Int64(s) => Some((*s.min_opt()?, *s.max_opt()?)),
+        _ => None,
+    }
+}
+
+// the range of a text column in a row group, `None` without statistics or for other types,
+// whose bytes don't order as the values do
+fn text_range(statistics: &Statistics) -> Option<(&[u8], &[u8])> {
+    match statistics {
+        Statistics::ByteArray(s) => Some((s.min_opt()?.data(), s.max_opt()?.data())),
+        Statistics::FixedLenByteArray(s) => Some((s.min_opt()?.data(), s.max_opt()?.data())),
+        _ => None,
+    }
+}
+
+// the times of day a time column spans in a row group as HHMMSSmmm, from HHMMSSmmm integers or
+// epoch timestamps within one day in exchange time; `None` for other formats
+fn time_range(column: &ColumnChunkMetaData, format: &TimeFormat) -> Option<(i64, i64)> {
+    let (min, max) = int_range(column.statistics()?)?;
+    let descr = column.column_descr();
+    // timestamp columns are read as such whatever the format says
+    let per_milli = match (descr.logical_type(), descr.converted_type(), format) {
+        (Some(LogicalType::Timestamp { unit: TimeUnit::MILLIS(_), .. }), _, _) => 1,
+        (Some(LogicalType::Timestamp { unit: TimeUnit::MICROS(_), .. }), _, _) => 1000,
+        (Some(LogicalType::Timestamp { .. }), _, _) => return None,
+        (_, ConvertedType::TIMESTAMP_MILLIS, _) => 1,
+        (_, ConvertedType::TIMESTAMP_MICROS, _) => 1000,
+        (_, _, TimeFormat::Compact) => return Some((min, max)),
+        (_, _, TimeFormat::EpochMillis) => 1,
+        (_, _, TimeFormat::EpochMicros) => 1000,
+        (_, _, TimeFormat::Pattern(_)) => return None,
+    };
+    let local = |value: i64| TIMEZONE.timestamp_millis_opt(value.div_euclid(per_milli)).single();
+    let (min, max) = (local(min)?, local(max)?);
+
+    (min.date_naive() == max.date_naive()).then(|| (n_time(min.time()), n_time(max.time())))
+}
+
+// whether a row group may hold rows passing `filter`, decided on the statistics of its symbol
+// and time columns; numeric symbols and times given as text are only filtered row by row
+fn may_match(group: &RowGroupMetaData, symbol: &str, time: &str, format: &TimeFormat, filter: &RowFilter) -> bool {
+    let column = |name: &str| group.columns().iter().find(|column| column.column_descr().name() == name);
+    if let (Some(wanted), Some((min, max))) = (
+        &filter.symbol,
+        column(symbol).and_then(|column| column.statistics()).and_then(text_range),
+    ) {
+        if wanted.as_bytes() < min || wanted.as_bytes() > max {
+            return false;
+        }
+    }
+    if let Some((min, max)) = column(time).and_then(|column| time_range(column, format)) {
+        let after = filter.hours.from.is_some_and(|from| max < n_time(from));
+        let before = filter.hours.to.is_some_and(|to| min >= n_time(to));
+        if after || before {
+            return false;
+        }
+    }
+
+    true
+}
+
+fn value(field: &Field) -> Result<Value<'_>, Error> {
+    Ok(match field {
+        Field::Byte(value) => Value::Integer(*value as i64),
+        Field::Short(value) => Value::Integer(*value as i64),
+        Field::Int(value) => Value::Integer(*value as i64),
+        Field::Long(value) => Value::Integer(*value),
+        Field::UByte(value) => Value::Integer(*value as i64),
+        Field::UShort(value) => Value::Integer(*value as i64),
+        Field::UInt(value) => Value::Integer(*value as i64),
+        Field::ULong(value) => Value::Integer(i64::try_from(*value)?),
+        Field::Float(value) => Value::Decimal(*value as f64),
+        Field::Double(value) => Value::Decimal(*value),
+        Field::Str(value) => Value::Text(value),
+        Field::Bytes(value) => Value::Text(str::from_utf8(value.data())?),
+        Field::Null => Value::Text(""),
+        Field::Date(days) => Value::Date(NaiveDate::default() + TimeDelta::days(*days as i64)),
+        Field::TimestampMillis(millis) => Value::Millis(*millis),
+        Field::TimestampMicros(micros) => Value::Millis(micros / 1000),
+        field => return Err(anyhow!("unexpected value `{}`", field)),
+    })
+}
+
+// the positions of the symbol and time in the columns asked of `Rows`
+const SYMBOL: usize = 0;
+const TIME: usize = 1;
+
+/// Rows of a Parquet file holding only the mapped columns. Row groups whose statistics rule out
+/// the filter are never read, the rest is filtered row by row.
+struct Rows {
+    rows: RowIter<'static>,
+    // where each column asked for is in a row, `None` if it is not mapped
+    positions: Vec<Option<usize>>,
+    // the date column comes last where the file has it
+    date: Option<usize>,
+    dates: TradingDate,
+    time_format: TimeFormat,
+    filter: RowFilter,
+    row: usize,
+}
+
+impl Rows {
+    // `columns` start with the symbol and the time, those left out by the schema are `None`
+    fn open(
+        path: &str,
+        columns: &[Option<&String>],
+        date_column: Option<&String>,
+        date: Option<NaiveDate>,
+        time_format: &TimeFormat,
+        filter: &RowFilter,
+    ) -> Result<Self, Error> {
+        let (symbol, time) = (columns[SYMBOL].cloned().unwrap_or_default(), columns[TIME].cloned().unwrap_or_default());
+        let predicate = {
+            let (format, filter) = (time_format.clone(), filter.clone());
+            move |group: &RowGroupMetaData, _: usize| may_match(group, &symbol, &time, &format, &filter)
+        };
+        let file = File::open(path).with_context(|| format!("open {}", path))?;
+        let options = ReadOptionsBuilder::new().with_predicate(Box::new(predicate)).build();
+        let reader = SerializedFileReader::new_with_options(file, options).with_context(|| format!("read {}", path))?;
+
+        let schema = reader.metadata().file_metadata().schema();
+        let field = |name: &String| schema.get_fields().iter().find(|field| field.name() == name.as_str()).cloned();
+        // like in the CSV, the date column may be left out
+        let date_column = date_column.filter(|name| field(name).is_some());
+        let fields = columns
+            .iter()
+            .flatten()
+            .chain(date_column.iter())
+            .map(|name| field(name).ok_or_else(|| anyhow!("no column `{}` in {}", name, path)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let mut mapped = 0;
+        let positions = columns
+            .iter()
+            .map(|column| {
+                column.map(|_| {
+                    mapped += 1;
+                    mapped - 1
+                })
+            })
+            .collect();
+        let projection = Type::group_type_builder(schema.name()).with_fields(fields).build()?;
+
+        Ok(Self {
+            rows: RowIter::from_file_into(Box::new(reader)).project(Some(projection))?,
+            positions,
+            date: date_column.map(|_| mapped),
+            dates: TradingDate::new(date, path),
+            time_format: time_format.clone(),
+            filter: filter.clone(),
+            row: 0,
+        })
+    }
+
+    // the value of the `column` asked for, `None` if it is not mapped
+    fn get<'a>(&self, fields: &'a [Field], column: usize) -> Result<Option<Value<'a>>, Error> {
+        self.positions[column].map(|position| value(&fields[position])).transpose()
+    }
+
+    // the time of a row if it passes the filter
+    fn passes(&self, fields: &[Field], date: impl FnOnce(Value) -> Result<Option<NaiveDate>, Error>) -> Result<Option<Time>, Error> {
+        let symbol = self.get(fields, SYMBOL)?.unwrap();
+        if self.filter.symbol.as_ref().is_some_and(|wanted| *wanted != symbol.text()) {
+            return Ok(None);
+        }
+        let date = self.dates.resolve_with(|| match self.date {
+            Some(position) => date(value(&fields[position])?),
+            None => Ok(None),
+        })?;
+        let time = self.time_format.time(self.get(fields, TIME)?.unwrap(), date)?;
+
+        Ok(Some(time).filter(|time| self.filter.hours.contains(*time)))
+    }
+
+    // the fields of the next row passing the filter and its time
+    fn next_row(
+        &mut self,
+        date: impl Fn(Value) -> Result<Option<NaiveDate>, Error>,
+    ) -> Option<Result<(Vec<Field>, Time), Error>> {
+        while let Some(row) = self.rows.next() {
+            self.row += 1;
+            let fields = match row {
+                Ok(row) => row.into_columns().into_iter().map(|(_, field)| field).collect::<Vec<_>>(),
+                Err(e) => return Some(Err(Error::from(e).context(format!("row {}", self.row)))),
+            };
+            match self.passes(&fields, &date) {
+                Ok(Some(time)) => return Some(Ok((fields, time))),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e.context(format!("row {}", self.row)))),
+            }
+        }
+
+        None
+    }
+}
+
+// tick columns after the symbol and time, followed by the ask prices, ask volumes,
+// bid prices and bid volumes of every level
+const STATUS: usize = 2;
+const PRICE: usize = 3;
+const HIGH_LIMITED: usize = 4;
+const LOW_LIMITED: usize = 5;
+const FIRST_LEVEL_COLUMN: usize = LOW_LIMITED + 1;
+
+/// Ticks read from a Parquet file with the columns of a schema.
+pub struct ParquetTicks {
+    rows: Rows,
+    schema: TickSchema,
+}
+
+impl ParquetTicks {
+    pub fn open(path: &str, date: Option<NaiveDate>, schema: &TickSchema, filter: &RowFilter) -> Result<Self, Error> {
+        let levels = [&schema.ask_price, &schema.ask_volume, &schema.bid_price, &schema.bid_volume]
+            .iter()
+            .flat_map(|pattern| (1..=schema.levels).map(move |level| level_column(pattern, level)))
+            .collect::<Vec<_>>();
+        let columns = [
+            Some(&schema.symbol),
+            Some(&schema.time),
+            schema.status.as_ref(),
+            Some(&schema.price),
+            Some(&schema.high_limited),
+            Some(&schema.low_limited),
+        ]
+        .iter()
+        .copied()
+        .chain(levels.iter().map(Some))
+        .collect::<Vec<_>>();
+
+        Ok(Self {
+            rows: Rows::open(path, &columns, schema.date.as_ref(), date, &schema.time_format, filter)
+                .with_context(|| format!("ticks {}", path))?,
+            schema: schema.clone(),
+        })
+    }
+
+    fn tick(&self, fields: &[Field], timestamp: Time) -> Result<Tick, Error> {
+        let (rows, schema) = (&self.rows, &self.schema);
+        let value = |column: usize| Ok::<_, Error>(rows.get(fields, column)?.unwrap());
+        let levels = |prices: usize| {
+            (0..schema.levels)
+                .map(|level| {
+                    Ok((
+                        value(prices + level)?.scaled(schema.price_multiplier)?,
+                        value(prices + schema.levels + level)?.integer()?,
+                    ))
+                })
+                .collect::<Result<Vec<_>, Error>>()
+        };
+
+        Ok(Tick {
+            symbol: value(SYMBOL)?.text().into_owned(),
+            timestamp,
+            new_price: value(PRICE)?.scaled(schema.price_multiplier)?,
+            asks: levels(FIRST_LEVEL_COLUMN)?,
+            bids: levels(FIRST_LEVEL_COLUMN + 2 * schema.levels)?,
+            high_limited: value(HIGH_LIMITED)?.scaled(schema.limit_multiplier)?,
+            low_limited: value(LOW_LIMITED)?.scaled(schema.limit_multiplier)?,
+            phase: match rows.get(fields, STATUS)? {
+                Some(status) => Phase::from_status(status.integer()?),
+                None => Phase::Continuous,
+            },
+        })
+    }
+}
+
+impl Iterator for ParquetTicks {
+    type Item = Result<Tick, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let schema = &self.schema;
+        let (fields, time) = match self.rows.next_row(|value| schema.date(value))? {
+            Ok(row) => row,
+            Err(e) => return Some(Err(e)),
+        };
+
+        Some(self.tick(&fields, time).with_context(|| format!("row {}", self.rows.row)))
+    }
+}
+
+/// Transactions read from a Parquet file with the columns of a schema.
+pub struct ParquetTransactions {
+    rows: Rows,
+    schema: TransactionSchema,
+}
+
+impl ParquetTransactions {
+    pub fn open(path: &str, date: Option<NaiveDate>, schema: &TransactionSchema, filter: &RowFilter) -> Result<Self, Error> {
+        // symbol, time, then the fields in the order of `transaction`
+        let columns = [
+            Some(&schema.symbol),
+            Some(&schema.time),
+            Some(&schema.index),
+            Some(&schema.price),
+            Some(&schema.volume),
+            Some(&schema.side),
+            schema.order_kind.as_ref(),
+            schema.function_code.as_ref(),
+            schema.ask_order.as_ref(),
+            schema.bid_order.as_ref(),
+        ];
+
+        Ok(Self {
+            rows: Rows::open(path, &columns, schema.date.as_ref(), date, &schema.time_format, filter)
+                .with_context(|| format!("transactions {}", path))?,
+            schema: schema.clone(),
+        })
+    }
+
+    fn transaction(&self, fields: &[Field], timestamp: Time) -> Result<Transaction, Error> {
+        let (rows, schema) = (&self.rows, &self.schema);
+        let value = |column: usize| Ok::<_, Error>(rows.get(fields, column)?.unwrap());
+        let code = |column: usize| Ok::<_, Error>(rows.get(fields, column)?.map(|code| code.text().into_owned()).unwrap_or_default());
+        let order = |column: usize| rows.get(fields, column)?.map_or(Ok(0), |order| order.integer());
+        let index = value(2)?.integer()?;
+
+        Ok(Transaction {
+            symbol: value(SYMBOL)?.text().into_owned(),
+            timestamp,
+            index,
+            price: value(3)?.scaled(schema.price_multiplier)?,
+            volume: value(4)?.integer()?,
+            direction: schema.sides.direction(value(5)?)
+                .map_err(|e| anyhow!("transaction #{}: {}", index, e))?,
+            order_kind: OrderKind::try_from(code(6)?.as_str())
+                .map_err(|e| anyhow!("transaction #{}: order kind: {}", index, e))?,
+            function_code: FunctionCode::try_from(code(7)?.as_str())
+                .map_err(|e| anyhow!("transaction #{}: function code: {}", index, e))?,
+            ask_order: order(8)?,
+            bid_order: order(9)?,
+        })
+    }
+}
+
+impl Iterator for ParquetTransactions {
+    type Item = Result<Transaction, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let schema = &self.schema;
+        let (fields, time) = match self.rows.next_row(|value| schema.date(value))? {
+            Ok(row) => row,
+            Err(e) => return Some(Err(e)),
+        };
+
+        Some(self.transaction(&fields, time).with_context(|| format!("row {}", self.rows.row)))
+    }
+}
+
+// the symbols in the tick file `path`, read from its symbol column only
+pub fn symbols(path: &str, schema: &TickSchema) -> Result<Vec<Symbol>, Error> {
+    let file = File::open(path).with_context(|| format!("open {}", path))?;
+    let reader = SerializedFileReader::new(file).with_context(|| format!("read {}", path))?;
+    let root = reader.metadata().file_metadata().schema();
+    let field = root.get_fields()
+        .iter()
+        .find(|field| field.name() == schema.symbol)
+        .cloned()
+        .ok_or_else(|| anyhow!("no column `{}` in {}", schema.symbol, path))?;
+    let projection = Type::group_type_builder(root.name()).with_fields(vec![field]).build()?;
+    let mut symbols = BTreeSet::new();
+    for row in RowIter::from_file_into(Box::new(reader)).project(Some(projection))? {
+        let row = row?;
+        if let Some((_, field)) = row.get_column_iter().next() {
+            symbols.insert(value(field)?.text().into_owned());
+        }
+    }
+
+    Ok(symbols.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use parquet::data_type::{ByteArray, ByteArrayType, Int32Type, Int64Type};
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+
+    use super::*;
+    use crate::utils::Direction;
+
+    enum Column {
+        Int32(Vec<i32>),
+        Int64(Vec<i64>),
+        Text(Vec<&'static str>),
+    }
+
+    // a file of `message` with a row group for each of `groups`
+    fn write(name: &str, message: &str, groups: Vec<Vec<Column>>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("quant-test-parquet-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let schema = Arc::new(parse_message_type(message).unwrap());
+        let mut writer = SerializedFileWriter::new(File::create(&path).unwrap(), schema, Default::default()).unwrap();
+        for group in groups {
+            let mut rows = writer.next_row_group().unwrap();
+            for column in group {
+                let mut writer = rows.next_column().unwrap().unwrap();
+                match column {
+                    Column::Int32(values) => writer.typed::<Int32Type>().write_batch(&values, None, None),
+                    Column::Int64(values) => writer.typed::<Int64Type>().write_batch(&values, None, None),
+                    Column::Text(values) => {
+                        let values = values.into_iter().map(ByteArray::from).collect::<Vec<_>>();
+                        writer.typed::<ByteArrayType>().write_batch(&values, None, None)
+                    }
+                }
+                .unwrap();
+                writer.close().unwrap();
+            }
+            rows.close().unwrap();
+        }
+        writer.close().unwrap();
+
+        path
+    }
+
+    fn groups(path: &Path) -> Vec<RowGroupMetaData> {
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        reader.metadata().row_groups().to_vec()
+    }
+
+    fn filter(symbol: Option<&str>, from: Option<u32>) -> RowFilter {
+        RowFilter {
+            symbol: symbol.map(str::to_string),
+            hours: TimeRange { from: from.and_then(|hour| NaiveTime::from_hms_opt(hour, 0, 0)), to: None },
+        }
+    }
+
+    fn ticks_file() -> PathBuf {
+        let message = "message ticks {
+            required binary chWindCode (UTF8);
+            required int32 nTime;
+            required int64 nPrice;
+            required int64 HighLimited;
+            required int64 LowLimited;
+            required int64 nAskPrice1;
+            required int64 nAskVolume1;
+            required int64 nBidPrice1;
+            required int64 nBidVolume1;
+        }";
+        // a morning of one symbol, then an afternoon of another
+        let group = |symbol: &'static str, times: Vec<i32>, price: i64| {
+            vec![
+                Column::Text(vec![symbol; times.len()]),
+                Column::Int32(times),
+                Column::Int64(vec![price, price + 100]),
+                Column::Int64(vec![price * 11 / 100; 2]),
+                Column::Int64(vec![price * 9 / 100; 2]),
+                Column::Int64(vec![price + 100, price + 200]),
+                Column::Int64(vec![300, 400]),
+                Column::Int64(vec![price - 100, price]),
+                Column::Int64(vec![500, 600]),
+            ]
+        };
+
+        write("ticks.parquet", message, vec![
+            group("600000.SH", vec![93000000, 93003000], 100_000),
+            group("601012.SH", vec![140000000, 140003000], 500_000),
+        ])
+    }
+
+    fn tick_schema() -> TickSchema {
+        TickSchema { date: None, status: None, levels: 1, ..TickSchema::default() }
+    }
+
+    fn read_ticks(path: &Path, filter: &RowFilter) -> Vec<Tick> {
+        let date = NaiveDate::from_ymd_opt(2021, 6, 4);
+        ParquetTicks::open(path.to_str().unwrap(), date, &tick_schema(), filter)
+            .unwrap()
+            .collect::<Result<Vec<_>, Error>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn ticks_are_mapped_and_pruned() {
+        let path = ticks_file();
+        let ticks = read_ticks(&path, &RowFilter::default());
+        assert_eq!(ticks.len(), 4);
+        let tick = &ticks[3];
+        assert_eq!(tick.symbol, "601012.SH");
+        assert_eq!(tick.timestamp, TIMEZONE.with_ymd_and_hms(2021, 6, 4, 14, 0, 3).unwrap());
+        assert_eq!(tick.new_price, 500_100);
+        assert_eq!((&tick.asks[..], &tick.bids[..]), (&[(500_200, 400)][..], &[(500_000, 600)][..]));
+        // limits are quoted in 1/1000 CNY
+        assert_eq!((tick.high_limited, tick.low_limited), (550_000, 450_000));
+        assert_eq!(tick.phase, Phase::Continuous);
+
+        let groups = groups(&path);
+        let (schema, format) = (tick_schema(), TimeFormat::Compact);
+        let may_match = |group: &RowGroupMetaData, filter: &RowFilter| may_match(group, &schema.symbol, &schema.time, &format, filter);
+        let symbol = filter(Some("601012.SH"), None);
+        assert!(!may_match(&groups[0], &symbol) && may_match(&groups[1], &symbol));
+        let afternoon = filter(None, Some(13));
+        assert!(!may_match(&groups[0], &afternoon) && may_match(&groups[1], &afternoon));
+        let morning = filter(Some("600000.SH"), Some(9));
+        assert!(may_match(&groups[0], &morning) && !may_match(&groups[1], &morning));
+
+        let ticks = read_ticks(&path, &symbol);
+        assert!(ticks.len() == 2 && ticks.iter().all(|tick| tick.symbol == "601012.SH"));
+        assert_eq!(read_ticks(&path, &afternoon).len(), 2);
+    }
+
+    #[test]
+    fn timestamp_columns_are_pruned() {
+        let message = "message transactions {
+            required binary Tkr (UTF8);
+            required int32 Date (DATE);
+            required int64 Time (TIMESTAMP(MILLIS, true));
+            required int64 Index;
+            required int64 Price;
+            required int64 Volume;
+            required binary BSFlag (UTF8);
+        }";
+        let date = NaiveDate::from_ymd_opt(2021, 6, 4).unwrap();
+        let days = (date - NaiveDate::default()).num_days() as i32;
+        let millis = |hour: u32, second: u32| TIMEZONE.with_ymd_and_hms(2021, 6, 4, hour, 0, second).unwrap().timestamp_millis();
+        let group = |hour: u32, index: i64| {
+            vec![
+                Column::Text(vec!["601012.SH"; 2]),
+                Column::Int32(vec![days; 2]),
+                Column::Int64(vec![millis(hour, 0), millis(hour, 1)]),
+                Column::Int64(vec![index, index + 1]),
+                Column::Int64(vec![500_000, 500_100]),
+                Column::Int64(vec![100, 200]),
+                Column::Text(vec!["B", "S"]),
+            ]
+        };
+        let path = write("transactions.parquet", message, vec![group(10, 1), group(14, 3)]);
+        let schema = TransactionSchema { order_kind: None, function_code: None, ask_order: None, bid_order: None, ..TransactionSchema::default() };
+
+        let groups = groups(&path);
+        let afternoon = filter(None, Some(13));
+        assert!(!may_match(&groups[0], &schema.symbol, &schema.time, &schema.time_format, &afternoon));
+        assert!(may_match(&groups[1], &schema.symbol, &schema.time, &schema.time_format, &afternoon));
+
+        let transactions = ParquetTransactions::open(path.to_str().unwrap(), None, &schema, &afternoon)
+            .unwrap()
+            .collect::<Result<Vec<_>, Error>>()
+            .unwrap();
+        assert_eq!(transactions.len(), 2);
+        let transaction = &transactions[1];
+        assert_eq!(transaction.symbol, "601012.SH");
+        assert_eq!(transaction.timestamp, TIMEZONE.with_ymd_and_hms(2021, 6, 4, 14, 0, 1).unwrap());
+        assert_eq!((transaction.index, transaction.price, transaction.volume), (4, 500_100, 200));
+        assert_eq!(transaction.direction, Direction::Sell);
+        assert_eq!((transaction.function_code, transaction.ask_order, transaction.bid_order), (FunctionCode::Trade, 0, 0));
+    }
+}
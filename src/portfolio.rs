@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use anyhow::{anyhow, Error};
+use serde::Serialize;
+
+use crate::broker::Fill;
+use crate::settlement::Inventory;
+use crate::strategy::StrategyConfig;
+use crate::utils::{serialize_time, Direction, Pnl, Price, Symbol, Time, Value, Volume};
+
+// one row per fill, the position columns are of the symbol filled
+#[derive(Debug, Clone, Serialize)]
+pub struct PortfolioSnapshot {
+    #[serde(serialize_with = "serialize_time")]
+    pub timestamp: Time,
+    pub symbol: Symbol,
+    pub position: Volume,
+    pub sellable_volume: Volume,
+    // goes below zero when the fee of a sale is more than its value
+    pub available_cash: Pnl,
+    pub frozen_cash: Value,
+    pub frozen_volume: Volume,
+    pub realized_pnl: Pnl,
+    pub unrealized_pnl: Pnl,
+}
+
+// the position in one symbol
+#[derive(Debug, Clone)]
+struct Holding {
+    position: Volume,
+    inventory: Inventory,
+    frozen_volume: Volume,
+    // buy volume resting in the book, counted against `max_position`
+    pending_volume: Volume,
+    // cost basis of the current position, fees included
+    cost: Value,
+    realized_pnl: Pnl,
+}
+
+impl Holding {
+    fn available_volume(&self) -> Volume {
+        self.inventory.sellable() - self.frozen_volume
+    }
+
+    fn unrealized_pnl(&self, mark_price: Price) -> Pnl {
+        (mark_price * self.position) as Pnl - self.cost as Pnl
+    }
+}
+
+/// Cash shared by every symbol and a position per symbol.
+#[derive(Debug)]
+pub struct Portfolio {
+    // per symbol
+    max_position: Volume,
+    available_cash: Pnl,
+    frozen_cash: Value,
+    // every symbol starts from the base position
+    base: Holding,
+    holdings: BTreeMap<Symbol, Holding>,
+    history: Vec<PortfolioSnapshot>,
+}
+
+impl Portfolio {
+    pub fn new(config: &StrategyConfig) -> Self {
+        Self {
+            max_position: config.max_position,
+            available_cash: config.initial_capital as Pnl,
+            frozen_cash: 0,
+            base: Holding {
+                position: config.base_position,
+                inventory: Inventory::new(config.settlement_rule, config.base_position),
+                frozen_volume: 0,
+                pending_volume: 0,
+                cost: config.base_cost_price * config.base_position,
+                realized_pnl: 0,
+            },
+            holdings: BTreeMap::new(),
+            history: Vec::new(),
+        }
+    }
+
+    fn holding(&self, symbol: &str) -> &Holding {
+        self.holdings.get(symbol).unwrap_or(&self.base)
+    }
+
+    fn holding_mut(&mut self, symbol: &str) -> &mut Holding {
+        if !self.holdings.contains_key(symbol) {
+            self.holdings.insert(symbol.to_string(), self.base.clone());
+        }
+        self.holdings.get_mut(symbol).unwrap()
+    }
+
+    // starts holding the base position of `symbol` once the engine sees it
+    pub fn track(&mut self, symbol: &str) {
+        self.holding_mut(symbol);
+    }
+
+    pub fn position(&self, symbol: &str) -> Volume {
+        self.holding(symbol).position
+    }
+
+    // shares of `symbol` that can still be sold today
+    pub fn available_volume(&self, symbol: &str) -> Volume {
+        self.holding(symbol).available_volume()
+    }
+
+    // shares bought before the new day become sellable
+    pub fn settle(&mut self) {
+        self.holdings.values_mut().for_each(|holding| holding.inventory.settle());
+    }
+
+    pub fn available_cash(&self) -> Pnl {
+        self.available_cash
+    }
+
+    pub fn history(&self) -> &[PortfolioSnapshot] {
+        &self.history
+    }
+
+    pub fn realized_pnl(&self, symbol: &str) -> Pnl {
+        self.holding(symbol).realized_pnl
+    }
+
+    pub fn unrealized_pnl(&self, symbol: &str, mark_price: Price) -> Pnl {
+        self.holding(symbol).unrealized_pnl(mark_price)
+    }
+
+    pub fn total_position(&self) -> Volume {
+        self.holdings.values().map(|holding| holding.position).sum()
+    }
+
+    pub fn total_unsettled_volume(&self) -> Volume {
+        self.holdings.values().map(|holding| holding.inventory.unsettled()).sum()
+    }
+
+    pub fn total_realized_pnl(&self) -> Pnl {
+        self.holdings.values().map(|holding| holding.realized_pnl).sum()
+    }
+
+    // every position marked at the price `mark` gives for its symbol
+    pub fn total_unrealized_pnl(&self, mark: impl Fn(&str) -> Price) -> Pnl {
+        self.holdings
+            .iter()
+            .map(|(symbol, holding)| holding.unrealized_pnl(mark(symbol)))
+            .sum()
+    }
+
+    pub fn snapshot(&self, timestamp: Time, symbol: &str, mark: impl Fn(&str) -> Price) -> PortfolioSnapshot {
+        let holding = self.holding(symbol);
+        PortfolioSnapshot {
+            timestamp,
+            symbol: symbol.to_string(),
+            position: holding.position,
+            sellable_volume: holding.inventory.sellable(),
+            available_cash: self.available_cash,
+            frozen_cash: self.frozen_cash,
+            frozen_volume: holding.frozen_volume,
+            realized_pnl: self.total_realized_pnl(),
+            unrealized_pnl: self.total_unrealized_pnl(mark),
+        }
+    }
+
+    // checks an order of `symbol` that trades immediately for `value` (fees included)
+    pub fn check(&self, symbol: &str, direction: Direction, volume: Volume, value: Value) -> Result<(), Error> {
+        let holding = self.holding(symbol);
+        match direction {
+            Direction::Buy => {
+                if value as Pnl > self.available_cash {
+                    return Err(anyhow!(
+                        "buy of {} {} needs {} cash but only {} is available",
+                        volume, symbol, value, self.available_cash,
+                    ));
+                }
+                if holding.position + holding.pending_volume + volume > self.max_position {
+                    return Err(anyhow!(
+                        "buy of {} {} exceeds position limit {} (position {}, pending {})",
+                        volume, symbol, self.max_position, holding.position, holding.pending_volume,
+                    ));
+                }
+            }
+            Direction::Sell => {
+                if volume > holding.available_volume() {
+                    return Err(anyhow!(
+                        "sell of {} {} exceeds sellable position {}",
+                        volume, symbol, holding.available_volume(),
+                    ));
+                }
+            }
+            Direction::Unknown => return Err(anyhow!("order of {} {} has no direction", volume, symbol)),
+        }
+
+        Ok(())
+    }
+
+    // reserves `cash` (buys) or shares (sells) for a resting limit order
+    pub fn freeze(&mut self, symbol: &str, direction: Direction, volume: Volume, cash: Value) -> Result<(), Error> {
+        self.check(symbol, direction, volume, cash)?;
+        match direction {
+            Direction::Buy => {
+                self.available_cash -= cash as Pnl;
+                self.frozen_cash += cash;
+                self.holding_mut(symbol).pending_volume += volume;
+            }
+            Direction::Sell => self.holding_mut(symbol).frozen_volume += volume,
+            Direction::Unknown => {}
+        }
+
+        Ok(())
+    }
+
+    // releases the reservation of a cancelled or filled part of a limit order
+    pub fn unfreeze(&mut self, symbol: &str, direction: Direction, volume: Volume, cash: Value) {
+        match direction {
+            Direction::Buy => {
+                self.frozen_cash -= cash;
+                self.available_cash += cash as Pnl;
+                self.holding_mut(symbol).pending_volume -= volume;
+            }
+            Direction::Sell => self.holding_mut(symbol).frozen_volume -= volume,
+            Direction::Unknown => {}
+        }
+    }
+
+    pub fn apply_fill(&mut self, fill: &Fill, mark: impl Fn(&str) -> Price) {
+        let holding = self.holding_mut(&fill.symbol);
+        match fill.direction {
+            Direction::Buy => {
+                holding.position += fill.volume;
+                holding.inventory.buy(fill.volume);
+                holding.cost += fill.value + fill.fee.total();
+                self.available_cash -= (fill.value + fill.fee.total()) as Pnl;
+            }
+            Direction::Sell => {
+                let cost = holding.cost * fill.volume / holding.position;
+                holding.position -= fill.volume;
+                holding.inventory.sell(fill.volume);
+                holding.cost -= cost;
+                // the minimum commission can be more than the value of a small sale
+                let proceeds = fill.value as Pnl - fill.fee.total() as Pnl;
+                holding.realized_pnl += proceeds - cost as Pnl;
+                self.available_cash += proceeds;
+            }
+            Direction::Unknown => {}
+        }
+        self.history.push(self.snapshot(fill.timestamp, &fill.symbol, mark));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::broker::Liquidity;
+    use crate::fee::FeeBreakdown;
+    use crate::utils::TIMEZONE;
+
+    #[test]
+    fn fee_above_value_debits_cash() {
+        let overrides = [("initial_capital", "0"), ("base_position", "100"), ("base_cost_price", "1")]
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        let config = StrategyConfig::new_from_file("no_such_config", &overrides).unwrap();
+        let mut portfolio = Portfolio::new(&config);
+        // 1 share at 1 CNY pays the 5 CNY minimum commission
+        let fill = Fill {
+            order_id: 0,
+            symbol: "600000.SH".to_string(),
+            timestamp: TIMEZONE.with_ymd_and_hms(2021, 6, 4, 10, 0, 0).unwrap(),
+            direction: Direction::Sell,
+            liquidity: Liquidity::Active,
+            price: 10000,
+            volume: 1,
+            value: 10000,
+            fee: FeeBreakdown { commission: 50000, stamp_duty: 5, transfer_fee: 1 },
+        };
+        portfolio.apply_fill(&fill, |_| 0);
+
+        assert_eq!(portfolio.available_cash(), -40006);
+        assert_eq!(portfolio.realized_pnl("600000.SH"), -50006);
+        assert_eq!(portfolio.position("600000.SH"), 99);
+    }
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+use crate::utils::Volume;
+
+/// How trades at the price of a resting order are shared between it and the displayed queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueModel {
+    // we join behind the displayed volume and fill once it is consumed
+    Fifo,
+    // every trade at the level is shared in proportion to the volumes resting there
+    ProRata,
+    // we are first in the queue
+    Optimistic,
+}
+
+// `ahead` is the volume of others in front of our order, `level` the volume of the whole level, ours excluded;
+// our own orders at a price are queued by the order book in the order they were placed
+impl QueueModel {
+    pub fn join(&self, level: Volume) -> Volume {
+        match self {
+            QueueModel::Fifo => level,
+            QueueModel::ProRata | QueueModel::Optimistic => 0,
+        }
+    }
+
+    // returns how much of `ours` fills when `traded`, what our earlier orders left of a trade, changes hands at our price
+    pub fn trade(&self, ahead: &mut Volume, level: Volume, traded: Volume, ours: Volume) -> Volume {
+        match self {
+            QueueModel::Fifo => {
+                let consumed = traded.min(*ahead);
+                *ahead -= consumed;
+                (traded - consumed).min(ours)
+            }
+            QueueModel::ProRata => match level + ours {
+                0 => 0,
+                total => (traded * ours / total).min(ours),
+            },
+            QueueModel::Optimistic => traded.min(ours),
+        }
+    }
+
+    // cancels are assumed to be spread evenly over the queue
+    pub fn cancel(&self, ahead: &mut Volume, level: Volume, cancelled: Volume) {
+        let cancelled = cancelled.min(level);
+        if cancelled > 0 {
+            *ahead -= cancelled * *ahead / level;
+        }
+    }
+
+    // a new snapshot shows `displayed` where the transactions since the last one left `expected`,
+    // either is `None` if the level is deeper than the book shows; the missing volume was cancelled
+    pub fn refresh(&self, ahead: &mut Volume, expected: Option<Volume>, displayed: Option<Volume>) {
+        if let Some(displayed) = displayed {
+            if let Some(expected) = expected.filter(|expected| displayed < *expected) {
+                self.cancel(ahead, expected, expected - displayed);
+            }
+            *ahead = (*ahead).min(displayed);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::str;
+use anyhow::{anyhow, Context, Error};
+use chrono::NaiveDate;
+use csv::ByteRecord;
+
+use crate::session::Phase;
+use crate::tick::Tick;
+use crate::transaction::{FunctionCode, OrderKind, Transaction};
+use crate::raw_data::TradingDate;
+use crate::schema::{level_column, TickSchema, TransactionSchema, Value};
+
+fn column(headers: &ByteRecord, name: &str) -> Result<usize, Error> {
+    optional_column(headers, name).ok_or_else(|| anyhow!("no column `{}`", name))
+}
+
+fn optional_column(headers: &ByteRecord, name: &str) -> Option<usize> {
+    headers.iter().position(|header| header == name.as_bytes())
+}
+
+fn field(record: &ByteRecord, index: usize) -> &[u8] {
+    record.get(index).unwrap_or_default()
+}
+
+fn text(field: &[u8]) -> Result<&str, Error> {
+    str::from_utf8(field).map_err(|_| anyhow!("`{}` is not utf-8", String::from_utf8_lossy(field)))
+}
+
+// unsigned integers straight from the bytes of a field
+fn number(field: &[u8]) -> Result<usize, Error> {
+    let invalid = || anyhow!("`{}` is not a number", String::from_utf8_lossy(field));
+    if field.is_empty() {
+        return Err(invalid());
+    }
+    field.iter().try_fold(0usize, |acc, byte| match byte {
+        b'0'..=b'9' => acc.checked_mul(10).and_then(|acc| acc.checked_add((byte - b'0') as usize)).ok_or_else(invalid),
+        _ => Err(invalid()),
+    })
+}
+
+// digits are read as an integer right away, anything else is left to the schema
+fn value(field: &[u8]) -> Result<Value<'_>, Error> {
+    match number(field) {
+        Ok(number) if number <= i64::MAX as usize => Ok(Value::Integer(number as i64)),
+        _ => Ok(Value::Text(text(field)?)),
+    }
+}
+
+fn open(path: &str) -> Result<(csv::Reader<File>, ByteRecord), Error> {
+    let mut reader = csv::Reader::from_path(path).with_context(|| format!("open {}", path))?;
+    let headers = reader.byte_headers()?.clone();
+
+    Ok((reader, headers))
+}
+
+// the column of `name` if the schema maps it, a mapped column must be in the file
+fn mapped_column(headers: &ByteRecord, name: &Option<String>) -> Result<Option<usize>, Error> {
+    name.as_ref().map(|name| column(headers, name)).transpose()
+}
+
+#[derive(Debug)]
+struct TickColumns {
+    symbol: usize,
+    date: Option<usize>,
+    time: usize,
+    status: Option<usize>,
+    price: usize,
+    asks: Vec<(usize, usize)>,
+    bids: Vec<(usize, usize)>,
+    high_limited: usize,
+    low_limited: usize,
+}
+
+impl TickColumns {
+    fn new(headers: &ByteRecord, schema: &TickSchema) -> Result<Self, Error> {
+        let levels = |prices: &str, volumes: &str| {
+            (1..=schema.levels)
+                .map(|level| Ok((column(headers, &level_column(prices, level))?, column(headers, &level_column(volumes, level))?)))
+                .collect::<Result<Vec<_>, Error>>()
+        };
+
+        Ok(Self {
+            symbol: column(headers, &schema.symbol)?,
+            // the date column may be left out of a file
+            date: schema.date.as_ref().and_then(|name| optional_column(headers, name)),
+            time: column(headers, &schema.time)?,
+            status: mapped_column(headers, &schema.status)?,
+            price: column(headers, &schema.price)?,
+            asks: levels(&schema.ask_price, &schema.ask_volume)?,
+            bids: levels(&schema.bid_price, &schema.bid_volume)?,
+            high_limited: column(headers, &schema.high_limited)?,
+            low_limited: column(headers, &schema.low_limited)?,
+        })
+    }
+}
+
+/// Reads ticks one `ByteRecord` at a time, the file is never held in memory.
+pub struct TickReader {
+    reader: csv::Reader<File>,
+    record: ByteRecord,
+    columns: TickColumns,
+    schema: TickSchema,
+    date: TradingDate,
+}
+
+impl TickReader {
+    pub fn open(path: &str, date: Option<NaiveDate>, schema: &TickSchema) -> Result<Self, Error> {
+        let (reader, headers) = open(path)?;
+
+        Ok(Self {
+            reader,
+            record: ByteRecord::new(),
+            columns: TickColumns::new(&headers, schema).with_context(|| format!("ticks {}", path))?,
+            schema: schema.clone(),
+            date: TradingDate::new(date, path),
+        })
+    }
+
+    fn tick(&mut self) -> Result<Tick, Error> {
+        let (record, columns, schema) = (&self.record, &self.columns, &self.schema);
+        let value = |index: usize| value(field(record, index));
+        let date = self.date.resolve_with(|| match columns.date {
+            Some(index) => schema.date(value(index)?),
+            None => Ok(None),
+        })?;
+        let levels = |ladder: &[(usize, usize)]| {
+            ladder
+                .iter()
+                .map(|(price, volume)| Ok((value(*price)?.scaled(schema.price_multiplier)?, value(*volume)?.integer()?)))
+                .collect::<Result<Vec<_>, Error>>()
+        };
+
+        Ok(Tick {
+            symbol: text(field(record, columns.symbol))?.to_string(),
+            timestamp: schema.time_format.time(value(columns.time)?, date)?,
+            new_price: value(columns.price)?.scaled(schema.price_multiplier)?,
+            asks: levels(&columns.asks)?,
+            bids: levels(&columns.bids)?,
+            high_limited: value(columns.high_limited)?.scaled(schema.limit_multiplier)?,
+            low_limited: value(columns.low_limited)?.scaled(schema.limit_multiplier)?,
+            phase: match columns.status {
+                Some(index) => Phase::from_status(value(index)?.integer()?),
+                None => Phase::Continuous,
+            },
+        })
+    }
+}
+
+impl Iterator for TickReader {
+    type Item = Result<Tick, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reader.read_byte_record(&mut self.record) {
+            Ok(true) => {
+                let line = self.record.position().map_or(0, |position| position.line());
+                Some(self.tick().with_context(|| format!("line {}", line)))
+            }
+            Ok(false) => None,
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TransactionColumns {
+    symbol: usize,
+    date: Option<usize>,
+    time: usize,
+    index: usize,
+    price: usize,
+    volume: usize,
+    side: usize,
+    order_kind: Option<usize>,
+    function_code: Option<usize>,
+    ask_order: Option<usize>,
+    bid_order: Option<usize>,
+}
+
+impl TransactionColumns {
+    fn new(headers: &ByteRecord, schema: &TransactionSchema) -> Result<Self, Error> {
+        Ok(Self {
+            symbol: column(headers, &schema.symbol)?,
+            date: schema.date.as_ref().and_then(|name| optional_column(headers, name)),
+            time: column(headers, &schema.time)?,
+            index: column(headers, &schema.index)?,
+            price: column(headers, &schema.price)?,
+            volume: column(headers, &schema.volume)?,
+            side: column(headers, &schema.side)?,
+            order_kind: mapped_column(headers, &schema.order_kind)?,
+            function_code: mapped_column(headers, &schema.function_code)?,
+            ask_order: mapped_column(headers, &schema.ask_order)?,
+            bid_order: mapped_column(headers, &schema.bid_order)?,
+        })
+    }
+}
+
+/// Reads transactions one `ByteRecord` at a time, the file is never held in memory.
+pub struct TransactionReader {
+    reader: csv::Reader<File>,
+    record: ByteRecord,
+    columns: TransactionColumns,
+    schema: TransactionSchema,
+    date: TradingDate,
+}
+
+impl TransactionReader {
+    pub fn open(path: &str, date: Option<NaiveDate>, schema: &TransactionSchema) -> Result<Self, Error> {
+        let (reader, headers) = open(path)?;
+
+        Ok(Self {
+            reader,
+            record: ByteRecord::new(),
+            columns: TransactionColumns::new(&headers, schema).with_context(|| format!("transactions {}", path))?,
+            schema: schema.clone(),
+            date: TradingDate::new(date, path),
+        })
+    }
+
+    fn transaction(&mut self) -> Result<Transaction, Error> {
+        let (record, columns, schema) = (&self.record, &self.columns, &self.schema);
+        let value = |index: usize| value(field(record, index));
+        let date = self.date.resolve_with(|| match columns.date {
+            Some(index) => schema.date(value(index)?),
+            None => Ok(None),
+        })?;
+        let index = value(columns.index)?.integer()?;
+        let code = |column: Option<usize>| column.map_or(Ok(""), |column| text(field(record, column)));
+        let order = |column: Option<usize>| column.map_or(Ok(0), |column| value(column)?.integer());
+
+        Ok(Transaction {
+            symbol: text(field(record, columns.symbol))?.to_string(),
+            timestamp: schema.time_format.time(value(columns.time)?, date)
+                .map_err(|e| anyhow!("transaction #{}: {}", index, e))?,
+            index,
+            price: value(columns.price)?.scaled(schema.price_multiplier)?,
+            volume: value(columns.volume)?.integer()?,
+            direction: schema.sides.direction(value(columns.side)?)
+                .map_err(|e| anyhow!("transaction #{}: {}", index, e))?,
+            order_kind: OrderKind::try_from(code(columns.order_kind)?)
+                .map_err(|e| anyhow!("transaction #{}: order kind: {}", index, e))?,
+            function_code: FunctionCode::try_from(code(columns.function_code)?)
+                .map_err(|e| anyhow!("transaction #{}: function code: {}", index, e))?,
+            ask_order: order(columns.ask_order)?,
+            bid_order: order(columns.bid_order)?,
+        })
+    }
+}
+
+impl Iterator for TransactionReader {
+    type Item = Result<Transaction, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reader.read_byte_record(&mut self.record) {
+            Ok(true) => {
+                let line = self.record.position().map_or(0, |position| position.line());
+                Some(self.transaction().with_context(|| format!("line {}", line)))
+            }
+            Ok(false) => None,
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use anyhow::{anyhow, Context, Error};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use config::{Config, File};
+use serde::Deserialize;
+
+use crate::utils::{time_parser, Direction, Time, TIMEZONE};
+
+// the most levels a snapshot is read with
+pub const MAX_LEVELS: usize = 10;
+
+/// A field of a data file, typed as far as the file format tells.
+#[derive(Debug, Clone, Copy)]
+pub enum Value<'a> {
+    Integer(i64),
+    Decimal(f64),
+    Text(&'a str),
+    Date(NaiveDate),
+    // epoch milliseconds, e.g. a Parquet timestamp
+    Millis(i64),
+}
+
+enum Number {
+    Integer(i64),
+    Decimal(f64),
+}
+
+impl Value<'_> {
+    // text holding a number is read as one
+    fn number(&self) -> Result<Number, Error> {
+        match self {
+            Value::Integer(value) | Value::Millis(value) => Ok(Number::Integer(*value)),
+            Value::Decimal(value) => Ok(Number::Decimal(*value)),
+            Value::Text(text) => {
+                let text = text.trim();
+                text.parse()
+                    .map(Number::Integer)
+                    .or_else(|_| text.parse().map(Number::Decimal))
+                    .map_err(|_| anyhow!("`{}` is not a number", text))
+            }
+            Value::Date(date) => Err(anyhow!("`{}` is not a number", date)),
+        }
+    }
+
+    pub fn integer(&self) -> Result<usize, Error> {
+        match self.number()? {
+            Number::Integer(value) => usize::try_from(value).map_err(|_| anyhow!("`{}` is negative", value)),
+            Number::Decimal(value) if value.fract() == 0.0 && value >= 0.0 => Ok(value as usize),
+            Number::Decimal(value) => Err(anyhow!("`{}` is not a whole number", value)),
+        }
+    }
+
+    // a price of the file in 1/10000 CNY
+    pub fn scaled(&self, multiplier: f64) -> Result<usize, Error> {
+        let value = match self.number()? {
+            // whole multipliers keep integer prices exact
+            Number::Integer(value) if multiplier.fract() == 0.0 && multiplier >= 1.0 => {
+                return u64::try_from(value)
+                    .ok()
+                    .and_then(|value| value.checked_mul(multiplier as u64))
+                    .map(|value| value as usize)
+                    .ok_or_else(|| anyhow!("bad price {}", value));
+            }
+            Number::Integer(value) => value as f64 * multiplier,
+            Number::Decimal(value) => value * multiplier,
+        };
+        if !value.is_finite() || value < 0.0 {
+            return Err(anyhow!("bad price {}", value));
+        }
+
+        Ok(value.round() as usize)
+    }
+
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            Value::Integer(value) | Value::Millis(value) => Cow::Owned(value.to_string()),
+            Value::Decimal(value) => Cow::Owned(value.to_string()),
+            Value::Text(text) => Cow::Borrowed(text),
+            Value::Date(date) => Cow::Owned(date.to_string()),
+        }
+    }
+}
+
+/// How the time column is written.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "String")]
+pub enum TimeFormat {
+    // `HHMMSSmmm`, an integer like `93000000`
+    Compact,
+    EpochMillis,
+    EpochMicros,
+    // a chrono pattern like `%H:%M:%S%.3f`, a date in it is ignored
+    Pattern(String),
+}
+
+impl From<String> for TimeFormat {
+    fn from(format: String) -> Self {
+        match format.as_str() {
+            "HHMMSSmmm" => Self::Compact,
+            "epoch_millis" => Self::EpochMillis,
+            "epoch_micros" => Self::EpochMicros,
+            _ => Self::Pattern(format),
+        }
+    }
+}
+
+impl TimeFormat {
+    // the time of day of `value` on the trading `date`, in exchange time; `Calendar::timestamp` moves night
+    // session rows off it
+    pub fn time(&self, value: Value, date: NaiveDate) -> Result<Time, Error> {
+        let millis = match (self, value) {
+            (_, Value::Millis(millis)) => millis,
+            (Self::Compact, value) => return time_parser(date, value.integer()?),
+            (Self::EpochMillis, value) => value.integer()? as i64,
+            (Self::EpochMicros, value) => value.integer()? as i64 / 1000,
+            (Self::Pattern(pattern), value) => {
+                let text = value.text();
+                let time = NaiveTime::parse_from_str(&text, pattern)
+                    .or_else(|_| NaiveDateTime::parse_from_str(&text, pattern).map(|time| time.time()))
+                    .map_err(|e| anyhow!("time `{}`: {}", text, e))?;
+                return on(date, time);
+            }
+        };
+        let time = TIMEZONE
+            .timestamp_millis_opt(millis)
+            .single()
+            .ok_or_else(|| anyhow!("bad timestamp {}", millis))?
+            .time();
+
+        on(date, time)
+    }
+}
+
+fn on(date: NaiveDate, time: NaiveTime) -> Result<Time, Error> {
+    TIMEZONE
+        .from_local_datetime(&date.and_time(time))
+        .single()
+        .ok_or_else(|| anyhow!("{} {} is not a valid time", date, time))
+}
+
+// the date of a date column, `None` where it is empty or zero
+fn date(value: Value, format: &str) -> Result<Option<NaiveDate>, Error> {
+    match value {
+        Value::Date(date) => return Ok(Some(date)),
+        Value::Integer(0) => return Ok(None),
+        _ => {}
+    }
+    let text = value.text();
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+
+    NaiveDate::parse_from_str(text.trim(), format)
+        .map(Some)
+        .map_err(|e| anyhow!("date {}: {}", text, e))
+}
+
+// the column of `level`, counted from 1, in a pattern like `nAskPrice{}`
+pub fn level_column(pattern: &str, level: usize) -> String {
+    pattern.replace("{}", &level.to_string())
+}
+
+/// The values the side column takes for each `Direction`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Sides {
+    pub buy: Vec<String>,
+    pub sell: Vec<String>,
+    pub unknown: Vec<String>,
+}
+
+impl Default for Sides {
+    fn default() -> Self {
+        Self {
+            buy: vec!["B".to_string()],
+            sell: vec!["S".to_string()],
+            unknown: vec!["".to_string(), "N".to_string()],
+        }
+    }
+}
+
+impl Sides {
+    pub fn direction(&self, value: Value) -> Result<Direction, Error> {
+        let text = value.text();
+        let is = |values: &[String]| values.iter().any(|value| value == text.trim());
+        if is(&self.buy) {
+            Ok(Direction::Buy)
+        } else if is(&self.sell) {
+            Ok(Direction::Sell)
+        } else if is(&self.unknown) {
+            Ok(Direction::Unknown)
+        } else {
+            Err(anyhow!("unexpected direction `{}`", text))
+        }
+    }
+}
+
+/// Where ticks keep each field and how, level columns are patterns where `{}` is the level from 1.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TickSchema {
+    pub symbol: String,
+    // optional in the file, the trading date is taken from the file path or `--date` without it
+    pub date: Option<String>,
+    pub date_format: String,
+    pub time: String,
+    pub time_format: TimeFormat,
+    // the exchange status code, the phase is left to the calendar without it
+    pub status: Option<String>,
+    pub price: String,
+    pub levels: usize,
+    pub ask_price: String,
+    pub ask_volume: String,
+    pub bid_price: String,
+    pub bid_volume: String,
+    pub high_limited: String,
+    pub low_limited: String,
+    // turn the prices of the file into 1/10000 CNY
+    pub price_multiplier: f64,
+    pub limit_multiplier: f64,
+}
+
+impl Default for TickSchema {
+    fn default() -> Self {
+        Self {
+            symbol: "chWindCode".to_string(),
+            date: Some("nActionDay".to_string()),
+            date_format: "%Y%m%d".to_string(),
+            time: "nTime".to_string(),
+            time_format: TimeFormat::Compact,
+            status: Some("Status".to_string()),
+            price: "nPrice".to_string(),
+            levels: MAX_LEVELS,
+            ask_price: "nAskPrice{}".to_string(),
+            ask_volume: "nAskVolume{}".to_string(),
+            bid_price: "nBidPrice{}".to_string(),
+            bid_volume: "nBidVolume{}".to_string(),
+            high_limited: "HighLimited".to_string(),
+            low_limited: "LowLimited".to_string(),
+            price_multiplier: 1.0,
+            // limit prices are quoted in 1/1000 CNY
+            limit_multiplier: 10.0,
+        }
+    }
+}
+
+impl TickSchema {
+    pub fn date(&self, value: Value) -> Result<Option<NaiveDate>, Error> {
+        date(value, &self.date_format)
+    }
+}
+
+/// Where transactions keep each field and how.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TransactionSchema {
+    pub symbol: String,
+    pub date: Option<String>,
+    pub date_format: String,
+    pub time: String,
+    pub time_format: TimeFormat,
+    pub index: String,
+    pub price: String,
+    pub volume: String,
+    pub side: String,
+    pub sides: Sides,
+    // without these every transaction is a trade of no particular order kind or orders
+    pub order_kind: Option<String>,
+    pub function_code: Option<String>,
+    pub ask_order: Option<String>,
+    pub bid_order: Option<String>,
+    pub price_multiplier: f64,
+}
+
+impl Default for TransactionSchema {
+    fn default() -> Self {
+        Self {
+            symbol: "Tkr".to_string(),
+            date: Some("Date".to_string()),
+            date_format: "%Y%m%d".to_string(),
+            time: "Time".to_string(),
+            time_format: TimeFormat::Compact,
+            index: "Index".to_string(),
+            price: "Price".to_string(),
+            volume: "Volume".to_string(),
+            side: "BSFlag".to_string(),
+            sides: Sides::default(),
+            order_kind: Some("OrderKind".to_string()),
+            function_code: Some("FunctionCode".to_string()),
+            ask_order: Some("AskOrder".to_string()),
+            bid_order: Some("BidOrder".to_string()),
+            price_multiplier: 1.0,
+        }
+    }
+}
+
+impl TransactionSchema {
+    pub fn date(&self, value: Value) -> Result<Option<NaiveDate>, Error> {
+        date(value, &self.date_format)
+    }
+}
+
+/// How the tick and transaction files of a vendor map onto `Tick` and `Transaction`, Wind by default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Schema {
+    pub ticks: TickSchema,
+    pub transactions: TransactionSchema,
+}
+
+impl Schema {
+    // a built-in schema by name or the TOML file at `name`, where an empty name leaves out an optional column
+    pub fn load(name: &str) -> Result<Self, Error> {
+        let mut schema = match name {
+            "wind" => Self::default(),
+            path => {
+                let mut s = Config::new();
+                s.merge(File::with_name(path)).with_context(|| format!("read {}", path))?;
+                s.try_into::<Self>().with_context(|| format!("read {}", path))?
+            }
+        };
+        let (ticks, transactions) = (&mut schema.ticks, &mut schema.transactions);
+        let optional = [
+            &mut ticks.date,
+            &mut ticks.status,
+            &mut transactions.date,
+            &mut transactions.order_kind,
+            &mut transactions.function_code,
+            &mut transactions.ask_order,
+            &mut transactions.bid_order,
+        ];
+        IntoIterator::into_iter(optional).for_each(|column| *column = column.take().filter(|name| !name.is_empty()));
+        if !(1..=MAX_LEVELS).contains(&schema.ticks.levels) {
+            return Err(anyhow!("{} levels, at most {} are read", schema.ticks.levels, MAX_LEVELS));
+        }
+
+        Ok(schema)
+    }
+}
@@ -0,0 +1,376 @@
+use std::collections::BTreeMap;
+use anyhow::{anyhow, Error};
+use chrono::{Datelike, NaiveDate, NaiveTime, TimeDelta, TimeZone, Timelike, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::tick::Tick;
+use crate::utils::{Time, TIMEZONE};
+
+const DAY: i64 = 24 * 3600 * 1000;
+
+/// Trading phase of the day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    PreOpen,
+    // e.g. SSE 9:15 - 9:25, orders are matched at one uncrossing price at its end
+    OpeningAuction,
+    // between sessions, e.g. the lunch break
+    Break,
+    Continuous,
+    // e.g. SSE 14:57 - 15:00, uncrossed at its end
+    ClosingAuction,
+    // e.g. STAR market fixed-price trading at the close after 15:05
+    AfterHours,
+    Closed,
+}
+
+impl Phase {
+    // the Wind `Status` of a snapshot; continuous trading is refined by the trading session,
+    // since Wind shows the closing auction as continuous trading
+    pub fn from_status(status: usize) -> Self {
+        match status as u8 {
+            b'F' => Phase::PreOpen,
+            b'I' => Phase::OpeningAuction,
+            b'P' => Phase::Break,
+            b'C' => Phase::Closed,
+            _ => Phase::Continuous,
+        }
+    }
+
+    pub fn is_auction(&self) -> bool {
+        matches!(self, Phase::OpeningAuction | Phase::ClosingAuction)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub phase: Phase,
+    pub start: NaiveTime,
+    // exclusive, a session ending before it starts runs past midnight
+    pub end: NaiveTime,
+}
+
+impl Session {
+    fn new(phase: Phase, start: (u32, u32), end: (u32, u32)) -> Self {
+        Self {
+            phase,
+            start: NaiveTime::from_hms_opt(start.0, start.1, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(end.0, end.1, 0).unwrap(),
+        }
+    }
+}
+
+// the sessions of the built-in products
+fn preset(product: &str) -> Option<Vec<Session>> {
+    use Phase::*;
+
+    let sse = vec![
+        Session::new(OpeningAuction, (9, 15), (9, 25)),
+        Session::new(Continuous, (9, 30), (11, 30)),
+        Session::new(Continuous, (13, 0), (14, 57)),
+        Session::new(ClosingAuction, (14, 57), (15, 0)),
+    ];
+    match product {
+        "sse_a_share" => Some(sse),
+        "star_market" => Some(sse.into_iter().chain([Session::new(AfterHours, (15, 5), (15, 30))]).collect()),
+        "shfe_futures" => Some(vec![
+            Session::new(Continuous, (21, 0), (2, 30)),
+            Session::new(OpeningAuction, (8, 55), (8, 59)),
+            Session::new(Continuous, (9, 0), (10, 15)),
+            Session::new(Continuous, (10, 30), (11, 30)),
+            Session::new(Continuous, (13, 30), (15, 0)),
+        ]),
+        "hk_stock" => Some(vec![
+            Session::new(OpeningAuction, (9, 0), (9, 20)),
+            Session::new(Continuous, (9, 30), (12, 0)),
+            Session::new(Continuous, (13, 0), (16, 0)),
+            Session::new(ClosingAuction, (16, 0), (16, 10)),
+        ]),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CalendarConfig {
+    // a product of `products` or one of the built-in ones
+    pub product: String,
+    pub products: BTreeMap<String, Vec<Session>>,
+    pub holidays: Vec<NaiveDate>,
+    // trading ends at the given time on these dates
+    pub half_days: BTreeMap<NaiveDate, NaiveTime>,
+}
+
+impl Default for CalendarConfig {
+    fn default() -> Self {
+        Self {
+            product: "sse_a_share".to_string(),
+            products: BTreeMap::new(),
+            holidays: Vec::new(),
+            half_days: BTreeMap::new(),
+        }
+    }
+}
+
+// milliseconds since midnight
+fn millis(time: NaiveTime) -> i64 {
+    time.num_seconds_from_midnight() as i64 * 1000 + (time.nanosecond() / 1_000_000) as i64
+}
+
+/// The sessions of the configured product and the days it doesn't trade.
+#[derive(Debug, Clone)]
+pub struct Calendar {
+    sessions: Vec<(Phase, i64, i64)>,
+    // the session running past midnight, traded on the evening before the trading day
+    night: Option<(i64, i64)>,
+    holidays: Vec<NaiveDate>,
+    half_days: BTreeMap<NaiveDate, i64>,
+}
+
+impl Calendar {
+    pub fn new(config: &CalendarConfig) -> Result<Self, Error> {
+        let sessions = config.products
+            .get(&config.product)
+            .cloned()
+            .or_else(|| preset(&config.product))
+            .ok_or_else(|| anyhow!("unknown product `{}`", config.product))?;
+        let sessions = sessions
+            .into_iter()
+            .map(|session| (session.phase, millis(session.start), millis(session.end)))
+            .collect::<Vec<_>>();
+
+        Ok(Self {
+            night: sessions.iter().find(|(_, start, end)| end < start).map(|(_, start, end)| (*start, *end)),
+            sessions,
+            holidays: config.holidays.clone(),
+            half_days: config.half_days.iter().map(|(date, close)| (*date, millis(*close))).collect(),
+        })
+    }
+
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        !self.holidays.contains(&date) && !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+    }
+
+    // the trading day before `date`, a week of holidays at most
+    fn previous_trading_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut day = date.pred_opt().unwrap();
+        for _ in 0..14 {
+            if self.is_trading_day(day) {
+                break;
+            }
+            day = day.pred_opt().unwrap();
+        }
+
+        day
+    }
+
+    // the first trading day from `date` on
+    fn next_trading_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut day = date;
+        for _ in 0..14 {
+            if self.is_trading_day(day) {
+                break;
+            }
+            day = day.succ_opt().unwrap();
+        }
+
+        day
+    }
+
+    // moves a row stamped with the time of day on its trading date to when it traded: from the start
+    // of the night session on the evening of the trading day before, after midnight on the morning after it
+    pub fn timestamp(&self, stamped: Time) -> Result<Time, Error> {
+        let (date, time) = (stamped.date_naive(), stamped.time());
+        let t = millis(time);
+        let date = match self.night {
+            Some((start, _)) if t >= start => self.previous_trading_day(date),
+            Some((_, end)) if t < end => self.previous_trading_day(date).succ_opt().unwrap(),
+            _ => return Ok(stamped),
+        };
+        TIMEZONE
+            .from_local_datetime(&date.and_time(time))
+            .single()
+            .ok_or_else(|| anyhow!("{} {} is not a valid time", date, time))
+    }
+
+    // the trading day `timestamp` belongs to, the next one for the night session
+    pub fn trading_date(&self, timestamp: Time) -> NaiveDate {
+        let (date, t) = (timestamp.date_naive(), millis(timestamp.time()));
+        match self.night {
+            Some((start, _)) if t >= start => self.next_trading_day(date.succ_opt().unwrap()),
+            Some((_, end)) if t < end => self.next_trading_day(date),
+            _ => date,
+        }
+    }
+
+    pub fn session(&self, date: NaiveDate) -> Result<TradingSession, Error> {
+        if !self.is_trading_day(date) {
+            return Err(anyhow!("{} is not a trading day", date));
+        }
+        let mut sessions = self.sessions.clone();
+        if let Some(close) = self.half_days.get(&date).copied() {
+            sessions.retain(|(_, start, end)| *start < close || end < start);
+            sessions.iter_mut().for_each(|(_, start, end)| {
+                if *start < *end && *end > close {
+                    *end = close;
+                }
+            });
+        }
+
+        Ok(TradingSession { date, sessions })
+    }
+
+    // continuous trading of the trading day `date` in exchange time, the night session on the evening before
+    fn continuous(&self, date: NaiveDate) -> Vec<(Time, Time)> {
+        let sessions = match self.session(date) {
+            Ok(session) => session.sessions,
+            Err(_) => return Vec::new(),
+        };
+        let evening = self.previous_trading_day(date);
+        let at = |day: NaiveDate, t: i64| {
+            TIMEZONE
+                .from_local_datetime(&day.and_time(NaiveTime::MIN))
+                .single()
+                .map(|midnight| midnight + TimeDelta::milliseconds(t))
+        };
+        let mut intervals = sessions
+            .iter()
+            .filter(|(phase, _, _)| *phase == Phase::Continuous)
+            .filter_map(|(_, start, end)| match start < end {
+                true => Some((at(date, *start)?, at(date, *end)?)),
+                false => Some((at(evening, *start)?, at(evening, DAY + end)?)),
+            })
+            .collect::<Vec<_>>();
+        intervals.sort_unstable();
+
+        intervals
+    }
+
+    // the time `duration` of continuous trading after `from`, carried over to the next trading day
+    // past the close, so weekends and holidays don't count
+    pub fn advance(&self, from: Time, duration: TimeDelta) -> Time {
+        let mut left = duration;
+        let mut date = self.trading_date(from);
+        // a month of trading days at most
+        for _ in 0..31 {
+            for (start, end) in self.continuous(date) {
+                if end <= from {
+                    continue;
+                }
+                let start = start.max(from);
+                if left <= end - start {
+                    return start + left;
+                }
+                left -= end - start;
+            }
+            date = self.next_trading_day(date.succ_opt().unwrap());
+        }
+
+        from + duration
+    }
+
+    // the schedule decides the phase of every snapshot, except that a snapshot reported out of
+    // continuous trading during a continuous session keeps its status, e.g. a halt;
+    // `session` caches the session of the previous snapshot
+    pub fn assign_phase(&self, session: &mut Option<TradingSession>, tick: &mut Tick) -> Result<(), Error> {
+        let date = self.trading_date(tick.timestamp);
+        if session.as_ref().map(|session| session.date) != Some(date) {
+            *session = Some(self.session(date)?);
+        }
+        tick.phase = match (session.as_ref().unwrap().phase_at(tick.timestamp), tick.phase) {
+            (Phase::Continuous, reported) => reported,
+            (scheduled, _) => scheduled,
+        };
+
+        Ok(())
+    }
+}
+
+/// The sessions of one trading day, in milliseconds since midnight.
+#[derive(Debug, Clone)]
+pub struct TradingSession {
+    date: NaiveDate,
+    sessions: Vec<(Phase, i64, i64)>,
+}
+
+impl TradingSession {
+    pub fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    fn session_at(&self, t: i64) -> Option<Phase> {
+        self.sessions
+            .iter()
+            .find(|(_, start, end)| match start < end {
+                true => (*start..*end).contains(&t),
+                false => t >= *start || t < *end,
+            })
+            .map(|(phase, _, _)| *phase)
+    }
+
+    // the scheduled phase at `timestamp`, outside the sessions it is pre-open before the first one,
+    // closed after the last one and a break in between
+    pub fn phase_at(&self, timestamp: Time) -> Phase {
+        let t = millis(timestamp.time());
+        if let Some(phase) = self.session_at(t) {
+            return phase;
+        }
+        let day_sessions = self.sessions.iter().filter(|(_, start, end)| start < end);
+        match (day_sessions.clone().map(|s| s.1).min(), day_sessions.map(|s| s.2).max()) {
+            (Some(first), _) if t < first => Phase::PreOpen,
+            (_, Some(last)) if t >= last => Phase::Closed,
+            (None, None) => Phase::Closed,
+            _ => Phase::Break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamped(date: (i32, u32, u32), time: (u32, u32)) -> Time {
+        TIMEZONE.with_ymd_and_hms(date.0, date.1, date.2, time.0, time.1, 0).unwrap()
+    }
+
+    #[test]
+    fn night_session_trades_before_day_session() {
+        let calendar = Calendar::new(&CalendarConfig {
+            product: "shfe_futures".to_string(),
+            ..CalendarConfig::default()
+        })
+        .unwrap();
+        // rows of Monday 2021-06-07 in file order, the night session opened on Friday evening
+        let rows = [(21, 0), (23, 59), (1, 30), (9, 0), (14, 59)]
+            .iter()
+            .map(|time| calendar.timestamp(stamped((2021, 6, 7), *time)).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(rows[0], stamped((2021, 6, 4), (21, 0)));
+        assert_eq!(rows[2], stamped((2021, 6, 5), (1, 30)));
+        assert_eq!(rows[3], stamped((2021, 6, 7), (9, 0)));
+        assert!(rows.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(rows.iter().all(|row| calendar.trading_date(*row) == NaiveDate::from_ymd_opt(2021, 6, 7).unwrap()));
+    }
+
+    #[test]
+    fn advance_skips_non_trading_days() {
+        let calendar = |product: &str, holidays: Vec<NaiveDate>| {
+            Calendar::new(&CalendarConfig { product: product.to_string(), holidays, ..CalendarConfig::default() }).unwrap()
+        };
+        let minutes = TimeDelta::minutes;
+        let sse = calendar("sse_a_share", Vec::new());
+        // within the day, across the lunch break, and from a Friday afternoon to Monday morning
+        assert_eq!(sse.advance(stamped((2021, 6, 4), (10, 0)), minutes(5)), stamped((2021, 6, 4), (10, 5)));
+        assert_eq!(sse.advance(stamped((2021, 6, 4), (11, 29)), minutes(2)), stamped((2021, 6, 4), (13, 1)));
+        assert_eq!(sse.advance(stamped((2021, 6, 4), (14, 50)), minutes(20)), stamped((2021, 6, 7), (9, 43)));
+        // the closing auction does not count, a Monday holiday moves it to Tuesday
+        let holiday = calendar("sse_a_share", vec![NaiveDate::from_ymd_opt(2021, 6, 7).unwrap()]);
+        assert_eq!(holiday.advance(stamped((2021, 6, 4), (14, 58)), minutes(1)), stamped((2021, 6, 8), (9, 31)));
+        // the night session of Monday opens on Friday evening
+        let shfe = calendar("shfe_futures", Vec::new());
+        assert_eq!(shfe.advance(stamped((2021, 6, 4), (14, 55)), minutes(10)), stamped((2021, 6, 4), (21, 5)));
+        assert_eq!(shfe.advance(stamped((2021, 6, 5), (2, 20)), minutes(20)), stamped((2021, 6, 7), (9, 10)));
+    }
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+use crate::utils::Volume;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettlementRule {
+    // bought shares can be sold right away
+    T0,
+    // bought shares can only be sold from the next trading day on (A-shares)
+    T1,
+}
+
+/// Splits a position into shares that may be sold today and shares still waiting for settlement.
+#[derive(Debug, Clone)]
+pub struct Inventory {
+    rule: SettlementRule,
+    sellable: Volume,
+    unsettled: Volume,
+}
+
+impl Inventory {
+    pub fn new(rule: SettlementRule, base_position: Volume) -> Self {
+        Self {
+            rule,
+            sellable: base_position,
+            unsettled: 0,
+        }
+    }
+
+    pub fn sellable(&self) -> Volume {
+        self.sellable
+    }
+
+    pub fn unsettled(&self) -> Volume {
+        self.unsettled
+    }
+
+    pub fn buy(&mut self, volume: Volume) {
+        match self.rule {
+            SettlementRule::T0 => self.sellable += volume,
+            SettlementRule::T1 => self.unsettled += volume,
+        }
+    }
+
+    pub fn sell(&mut self, volume: Volume) {
+        self.sellable -= volume;
+    }
+
+    // called when a new trading day starts
+    pub fn settle(&mut self) {
+        self.sellable += self.unsettled;
+        self.unsettled = 0;
+    }
+}
@@ -1,20 +1,45 @@
-use std::sync::{Arc, Mutex};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::time::{Duration, SystemTime};
+use anyhow::Error;
+use chrono::{NaiveDate, TimeDelta};
 use config::{Config, ConfigError, File};
-use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use crate::dataset::Day;
+use crate::broker::{Broker, Fill, Liquidity, OrderType, RemainderPolicy, SealedPolicy, TimerToken};
+use crate::event::Event;
+use crate::fee::{FeeBreakdown, FeeModel};
+use crate::impact::ImpactConfig;
 use crate::tick::Tick;
 use crate::transaction::Transaction;
-use crate::utils::{Direction, Price, Time, Value, Volume};
+use crate::ledger::{build_round_trips, RoundTrip};
+use crate::portfolio::PortfolioSnapshot;
+use crate::queue::QueueModel;
+use crate::schema::Schema;
+use crate::session::{Calendar, CalendarConfig, Phase};
+use crate::settlement::SettlementRule;
+use crate::validation::ValidationConfig;
+use crate::utils::{Direction, Pnl, Price, Symbol, Value, Volume, PRICE_SCALE};
 
-#[derive(Debug)]
-pub struct Order {
-    timestamp: Time,
-    price: Price,
-    volume: Volume,
-    value: Value,
+pub trait Strategy {
+    fn on_tick(&mut self, _broker: &mut Broker, _tick: &Tick) {}
+
+    fn on_transaction(&mut self, _broker: &mut Broker, _transaction: &Transaction) {}
+
+    // cancellations don't trade but shrink the queue on `cancel_side`
+    fn on_cancel(&mut self, _broker: &mut Broker, _transaction: &Transaction) {}
+
+    fn on_fill(&mut self, _broker: &mut Broker, _fill: &Fill) {}
+
+    fn on_timer(&mut self, _broker: &mut Broker, _token: TimerToken) {}
+
+    // called with the phase of the first snapshot of each symbol and on every change after
+    fn on_phase(&mut self, _broker: &mut Broker, _symbol: &str, _phase: Phase) {}
+
+    // called after the last event of a day, orders still resting are dropped right after
+    fn on_day_end(&mut self, _broker: &mut Broker) {}
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,8 +51,25 @@ struct StrategyRawConfig {
     pub open_min_interval_sec: i32,
     pub limit_close_elapsed_sec: i32,
     pub close_waiting_elapsed_sec: i32,
-    pub active_fee_ratio: f64,
-    pub passive_fee_ratio: f64,
+    pub commission_ratio: f64,
+    pub min_commission: f64,
+    pub stamp_duty_ratio: f64,
+    pub transfer_fee_ratio: f64,
+    pub initial_capital: f64,
+    pub max_position: usize,
+    pub settlement_rule: SettlementRule,
+    pub base_position: usize,
+    pub base_cost_price: f64,
+    pub queue_model: QueueModel,
+    pub impact: ImpactConfig,
+    pub market_remainder: RemainderPolicy,
+    pub open_order_type: OrderType,
+    pub price_cage: bool,
+    pub limit_sealed: SealedPolicy,
+    pub calendar: CalendarConfig,
+    pub validation: ValidationConfig,
+    // a built-in schema of the data files or a TOML file describing one
+    pub schema: String,
 }
 
 impl Default for StrategyRawConfig {
@@ -39,90 +81,283 @@ impl Default for StrategyRawConfig {
             open_min_interval_sec: 30,
             limit_close_elapsed_sec: 60,
             close_waiting_elapsed_sec: 30,
-            active_fee_ratio: 0.02f64,
-            passive_fee_ratio: 0.015f64,
+            commission_ratio: 0.025f64,
+            min_commission: 5f64,
+            stamp_duty_ratio: 0.05f64,
+            transfer_fee_ratio: 0.001f64,
+            initial_capital: 1_000_000f64,
+            max_position: 10_000,
+            settlement_rule: SettlementRule::T0,
+            base_position: 0,
+            base_cost_price: 0f64,
+            queue_model: QueueModel::Fifo,
+            impact: ImpactConfig::default(),
+            market_remainder: RemainderPolicy::Cancel,
+            open_order_type: OrderType::FullDepth,
+            price_cage: true,
+            limit_sealed: SealedPolicy::Reject,
+            calendar: CalendarConfig::default(),
+            validation: ValidationConfig::default(),
+            schema: "wind".to_string(),
         }
     }
 }
 
 #[derive(Debug)]
 pub struct StrategyConfig {
-    rise_duration: Time,
-    rise_threshold: f64,
-    open_volume: Volume,
-    open_min_interval: Time,
-    limit_close_elapsed: Time,
-    close_waiting_elapsed: Time,
-    active_fee_ratio: f64,
-    passive_fee_ratio: f64,
+    pub rise_duration: TimeDelta,
+    pub rise_threshold: f64,
+    pub open_volume: Volume,
+    pub open_min_interval: TimeDelta,
+    pub limit_close_elapsed: TimeDelta,
+    pub close_waiting_elapsed: TimeDelta,
+    pub commission_ratio: f64,
+    pub min_commission: Value,
+    pub stamp_duty_ratio: f64,
+    pub transfer_fee_ratio: f64,
+    pub initial_capital: Value,
+    pub max_position: Volume,
+    pub settlement_rule: SettlementRule,
+    pub base_position: Volume,
+    pub base_cost_price: Price,
+    pub queue_model: QueueModel,
+    pub impact: ImpactConfig,
+    pub market_remainder: RemainderPolicy,
+    pub open_order_type: OrderType,
+    pub price_cage: bool,
+    pub limit_sealed: SealedPolicy,
+    pub calendar: Calendar,
+    pub validation: ValidationConfig,
+    pub schema: Schema,
 }
 
-impl From<StrategyRawConfig> for StrategyConfig {
-    fn from(config: StrategyRawConfig) -> Self {
-        Self {
-            rise_duration: config.rise_duration_min as Time * 60 * 1000,
+impl TryFrom<StrategyRawConfig> for StrategyConfig {
+    type Error = ConfigError;
+
+    fn try_from(config: StrategyRawConfig) -> Result<Self, Self::Error> {
+        let calendar = Calendar::new(&config.calendar)
+            .map_err(|e| ConfigError::Message(format!("calendar: {}", e)))?;
+        let schema = Schema::load(&config.schema)
+            .map_err(|e| ConfigError::Message(format!("schema: {:#}", e)))?;
+        Ok(Self {
+            rise_duration: TimeDelta::minutes(config.rise_duration_min as i64),
             rise_threshold: config.rise_threshold_percent / 100f64,
             open_volume: config.open_volume,
-            open_min_interval: config.open_min_interval_sec as Time * 1000,
-            limit_close_elapsed: config.limit_close_elapsed_sec as Time * 1000,
-            close_waiting_elapsed: config.close_waiting_elapsed_sec as Time * 1000,
-            active_fee_ratio: config.active_fee_ratio / 100f64,
-            passive_fee_ratio: config.passive_fee_ratio / 100f64,
-        }
+            open_min_interval: TimeDelta::seconds(config.open_min_interval_sec as i64),
+            limit_close_elapsed: TimeDelta::seconds(config.limit_close_elapsed_sec as i64),
+            close_waiting_elapsed: TimeDelta::seconds(config.close_waiting_elapsed_sec as i64),
+            commission_ratio: config.commission_ratio / 100f64,
+            min_commission: (config.min_commission * PRICE_SCALE as f64) as Value,
+            stamp_duty_ratio: config.stamp_duty_ratio / 100f64,
+            transfer_fee_ratio: config.transfer_fee_ratio / 100f64,
+            initial_capital: (config.initial_capital * PRICE_SCALE as f64) as Value,
+            max_position: config.max_position,
+            settlement_rule: config.settlement_rule,
+            base_position: config.base_position,
+            base_cost_price: (config.base_cost_price * PRICE_SCALE as f64) as Price,
+            queue_model: config.queue_model,
+            impact: config.impact,
+            market_remainder: config.market_remainder,
+            open_order_type: config.open_order_type,
+            price_cage: config.price_cage,
+            limit_sealed: config.limit_sealed,
+            calendar,
+            validation: config.validation,
+            schema,
+        })
     }
 }
 
 impl StrategyConfig {
-    pub fn new_from_file(path: &str) -> Result<StrategyConfig, ConfigError> {
+    // `overrides` are `(key, value)` pairs of `StrategyRawConfig` fields applied on top of the file,
+    // nested fields use dotted keys like `validation.crossed_book`
+    pub fn new_from_file(path: &str, overrides: &[(String, String)]) -> Result<StrategyConfig, ConfigError> {
         let mut s = Config::new();
         s.merge(File::with_name(path).required(false))?;
 
-        Ok(StrategyConfig::from(s.try_into::<StrategyRawConfig>()?))
+        let keys = serde_json::to_value(StrategyRawConfig::default())
+            .map_err(|e| ConfigError::Foreign(Box::new(e)))?;
+        for (key, value) in overrides {
+            if keys.pointer(&format!("/{}", key.replace('.', "/"))).is_none() {
+                return Err(ConfigError::Message(format!("unknown config key `{}`", key)));
+            }
+            s.set(key, value.as_str())?;
+        }
+
+        StrategyConfig::try_from(s.try_into::<StrategyRawConfig>()?)
     }
 }
 
 #[derive(Debug)]
-pub struct StrategyContext {
-    pub ticks: Vec<Tick>,
-    pub transactions: Vec<Transaction>,
+pub struct StrategyContext<S: Strategy> {
     pub config: StrategyConfig,
+    pub fee_model: Box<dyn FeeModel>,
+    pub strategy: S,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyResult {
+    pub date: NaiveDate,
+    pub open_times: usize,
+    pub close_times: usize,
+    pub traded_value: Value,
+    pub fee: Value,
+    // realized during the day
+    pub realized_pnl: Pnl,
+    // of the position carried over night
+    pub unrealized_pnl: Pnl,
+    pub position: Volume,
+    pub cash: Pnl,
+}
+
+impl DailyResult {
+    // `first_fill` is the index of the first fill of the day, `realized_pnl` the realized pnl before it
+    fn new(date: NaiveDate, broker: &Broker, first_fill: usize, realized_pnl: Pnl) -> Self {
+        let fills = &broker.fills()[first_fill..];
+        let portfolio = broker.portfolio();
+
+        DailyResult {
+            date,
+            open_times: fills.iter().filter(|fill| fill.direction == Direction::Buy).count(),
+            close_times: fills.iter().filter(|fill| fill.direction == Direction::Sell).count(),
+            traded_value: fills.iter().map(|fill| fill.value).sum(),
+            fee: fills.iter().map(|fill| fill.fee.total()).sum(),
+            realized_pnl: portfolio.total_realized_pnl() - realized_pnl,
+            unrealized_pnl: portfolio.total_unrealized_pnl(|symbol| broker.mark(symbol)),
+            position: portfolio.total_position(),
+            cash: portfolio.available_cash(),
+        }
+    }
+}
+
+impl fmt::Display for DailyResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: open {}, close {}, traded {}, fee {}, realized pnl {}, unrealized pnl {}, position {}",
+            self.date,
+            self.open_times,
+            self.close_times,
+            self.traded_value,
+            self.fee,
+            self.realized_pnl,
+            self.unrealized_pnl,
+            self.position,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolResult {
+    pub symbol: Symbol,
+    pub open_times: usize,
+    // fees included
+    pub open_value: Value,
+    pub close_times: usize,
+    // fees deducted
+    pub close_value: Pnl,
+    pub fee: Value,
+    pub realized_pnl: Pnl,
+    pub unrealized_pnl: Pnl,
+    pub position: Volume,
+}
+
+impl SymbolResult {
+    fn new(symbol: &str, broker: &Broker) -> Self {
+        let fills = broker.fills().iter().filter(|fill| fill.symbol == symbol);
+        let opens = fills.clone().filter(|fill| fill.direction == Direction::Buy);
+        let closes = fills.clone().filter(|fill| fill.direction == Direction::Sell);
+        let portfolio = broker.portfolio();
+
+        SymbolResult {
+            symbol: symbol.to_string(),
+            open_times: opens.clone().count(),
+            open_value: opens.map(|fill| fill.value + fill.fee.total()).sum(),
+            close_times: closes.clone().count(),
+            close_value: closes.map(|fill| fill.value as Pnl - fill.fee.total() as Pnl).sum(),
+            fee: fills.map(|fill| fill.fee.total()).sum(),
+            realized_pnl: portfolio.realized_pnl(symbol),
+            unrealized_pnl: portfolio.unrealized_pnl(symbol, broker.mark(symbol)),
+            position: portfolio.position(symbol),
+        }
+    }
+}
+
+impl fmt::Display for SymbolResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: open {} for {}, close {} for {}, fee {}, realized pnl {}, unrealized pnl {}, position {}",
+            self.symbol,
+            self.open_times,
+            self.open_value,
+            self.close_times,
+            self.close_value,
+            self.fee,
+            self.realized_pnl,
+            self.unrealized_pnl,
+            self.position,
+        )
+    }
 }
 
 pub struct StrategyResult {
     pub open_times: usize,
     pub open_value: Value,
     pub close_active_traded_times: usize,
-    pub close_active_traded_value: Value,
+    pub close_active_traded_value: Pnl,
     pub close_passive_traded_times: usize,
-    pub close_passive_traded_value: Value,
+    pub close_passive_traded_value: Pnl,
     pub yield_rate: f64,
+    pub fee: FeeBreakdown,
+    pub rejected_buy_times: usize,
+    pub rejected_sell_times: usize,
+    pub blocked_open_times: usize,
+    pub blocked_close_times: usize,
+    pub position: Volume,
+    pub unsettled_position: Volume,
+    pub cash: Pnl,
+    pub realized_pnl: Pnl,
+    pub unrealized_pnl: Pnl,
+    pub portfolio_history: Vec<PortfolioSnapshot>,
+    pub round_trips: Vec<RoundTrip>,
+    pub daily: Vec<DailyResult>,
+    pub symbols: Vec<SymbolResult>,
     pub time_elapsed: Duration,
 }
 
 impl StrategyResult {
-    pub fn new(
-        open_orders: &[(usize, Order)],
-        active_traded_orders: &[Order],
-        passive_traded_orders: &[Order],
-        time_elapsed: Duration,
-    ) -> StrategyResult {
-        let open_times = open_orders.len();
-        let open_value = open_orders
+    pub fn new(broker: &Broker, daily: Vec<DailyResult>, time_elapsed: Duration) -> StrategyResult {
+        let fills = broker.fills();
+        let open_fills = fills
             .iter()
-            .fold(0usize, |acc, (_, order)| acc + order.value);
-
-        let close_active_traded_times = active_traded_orders.len();
-        let close_active_traded_value = active_traded_orders
+            .filter(|fill| fill.direction == Direction::Buy)
+            .collect::<Vec<_>>();
+        let open_times = open_fills.len();
+        let open_value = open_fills
             .iter()
-            .fold(0usize, |acc, order| acc + order.value);
+            .fold(0usize, |acc, fill| acc + fill.value + fill.fee.total());
 
-        let close_passive_traded_times = passive_traded_orders.len();
-        let close_passive_traded_value = passive_traded_orders
-            .iter()
-            .fold(0usize, |acc, order| acc + order.value);
+        let close_fills = |liquidity: Liquidity| {
+            fills
+                .iter()
+                .filter(move |fill| fill.direction == Direction::Sell && fill.liquidity == liquidity)
+        };
+        let close_active_traded_times = close_fills(Liquidity::Active).count();
+        let close_active_traded_value = close_fills(Liquidity::Active)
+            .fold(0 as Pnl, |acc, fill| acc + fill.value as Pnl - fill.fee.total() as Pnl);
+
+        let close_passive_traded_times = close_fills(Liquidity::Passive).count();
+        let close_passive_traded_value = close_fills(Liquidity::Passive)
+            .fold(0 as Pnl, |acc, fill| acc + fill.value as Pnl - fill.fee.total() as Pnl);
 
         let yield_rate = ((close_active_traded_value + close_passive_traded_value) as f64 - open_value as f64)/ open_value as f64;
 
+        let mut fee = FeeBreakdown::default();
+        fills.iter().for_each(|fill| fee += fill.fee);
+
+        let portfolio = broker.portfolio();
+
         StrategyResult {
             open_times,
             open_value,
@@ -131,6 +366,20 @@ impl StrategyResult {
             close_passive_traded_times,
             close_passive_traded_value,
             yield_rate,
+            fee,
+            rejected_buy_times: broker.rejected_buys(),
+            rejected_sell_times: broker.rejected_sells(),
+            blocked_open_times: broker.blocked_buys(),
+            blocked_close_times: broker.blocked_sells(),
+            position: portfolio.total_position(),
+            unsettled_position: portfolio.total_unsettled_volume(),
+            cash: portfolio.available_cash(),
+            realized_pnl: portfolio.total_realized_pnl(),
+            unrealized_pnl: portfolio.total_unrealized_pnl(|symbol| broker.mark(symbol)),
+            portfolio_history: portfolio.history().to_vec(),
+            round_trips: build_round_trips(fills, broker.links()),
+            daily,
+            symbols: broker.symbols().map(|symbol| SymbolResult::new(symbol, broker)).collect(),
             time_elapsed,
         }
     }
@@ -149,6 +398,20 @@ impl fmt::Display for StrategyResult {
             passive:
             \ttimes: {}
             \tvalue: {}
+            \nfee:
+            total: {}
+            commission: {}
+            stamp duty: {}
+            transfer fee: {}\nrejected:
+            buy: {}
+            sell: {}\nblocked by limit:
+            open: {}
+            close: {}\nportfolio:
+            position: {}
+            unsettled: {}
+            cash: {}
+            realized pnl: {}
+            unrealized pnl: {}
             ",
             self.time_elapsed,
             self.yield_rate * 100f64,
@@ -158,194 +421,95 @@ impl fmt::Display for StrategyResult {
             self.close_active_traded_value,
             self.close_passive_traded_times,
             self.close_passive_traded_value,
-        )
-    }
-}
-
-impl StrategyContext {
-    fn open_trigger(&self, index: usize, open_tick: &Tick, last_open: Time) -> bool {
-        if !open_tick.in_trading_time() || open_tick.timestamp - last_open <= self.config.open_min_interval {
-            return false;
-        }
-        let expect_price = (open_tick.new_price as f64) * (1f64 + self.config.rise_threshold);
-        for tick in self.ticks[..index].iter().rev() {
-            if open_tick.time_eplased(tick) > self.config.rise_duration {
-                break;
+            self.fee.total(),
+            self.fee.commission,
+            self.fee.stamp_duty,
+            self.fee.transfer_fee,
+            self.rejected_buy_times,
+            self.rejected_sell_times,
+            self.blocked_open_times,
+            self.blocked_close_times,
+            self.position,
+            self.unsettled_position,
+            self.cash,
+            self.realized_pnl,
+            self.unrealized_pnl,
+        )?;
+        if self.daily.len() > 1 {
+            writeln!(f, "\n[Daily]")?;
+            for day in self.daily.iter() {
+                writeln!(f, "{}", day)?;
             }
-            if tick.new_price as f64 >= expect_price {
-                return true;
+        }
+        if self.symbols.len() > 1 {
+            writeln!(f, "\n[Symbols]")?;
+            for symbol in self.symbols.iter() {
+                writeln!(f, "{}", symbol)?;
             }
         }
-        return false;
-    }
 
-    fn open_market_orders(&self) -> Vec<(usize, Order)> {
-        let mut market_open_orders = Vec::new();
-        let mut last_open: Time = 0;
-        // opening orders
-        self.ticks
-            .iter()
-            .enumerate()
-            .for_each(|(index, tick)| {
-                if self.open_trigger(index, tick, last_open) {
-                    let volume = self.config.open_volume;
-                    match tick.handle_market_order(volume, Direction::Buy) {
-                        Ok((price, value)) => {
-                            market_open_orders.push((
-                                index,
-                                Order {
-                                    timestamp: tick.timestamp,
-                                    price,
-                                    volume,
-                                    value,
-                                },
-                            ));
-                            last_open = tick.timestamp;
-                        }
-                        Err(e) => println!("error: {:?}, volume: {}", e, volume),
-                    }
-                }
-            });
-
-        market_open_orders
+        Ok(())
     }
+}
 
-    fn close_limit_pending_orders(&self, market_open_orders: &[(usize, Order)]) -> Arc<Mutex<Vec<(usize, Order)>>> {
-        let limit_pending_orders = Arc::new(Mutex::new(Vec::new()));
-        market_open_orders
-            .par_iter()
-            .for_each(|(index, opened_order)| {
-                let limit_pending_orders = limit_pending_orders.clone();
-                for (idx, tick) in self.ticks[*index..].iter().enumerate() {
-                    if tick.timestamp > opened_order.timestamp + self.config.limit_close_elapsed {
-                        let price = tick.get_first_ask_price().expect("ask plate is empty");
-                        let mut limit_pending_orders = limit_pending_orders.lock().unwrap();
-                        limit_pending_orders.push((
-                            *index + idx,
-                            Order {
-                                timestamp: tick.timestamp,
-                                price,
-                                volume: opened_order.volume,
-                                value: price * opened_order.volume,
-                            },
-                        ));
-                        break;
-                    }
-                }
-            });
+impl<S: Strategy> StrategyContext<S> {
+    fn dispatch_fills(strategy: &mut S, broker: &mut Broker) {
+        while let Some(fill) = broker.pop_fill() {
+            strategy.on_fill(broker, &fill);
+        }
+    }
 
-        limit_pending_orders
-            .lock()
-            .unwrap()
-            .sort_by_key(|(_, order)| order.timestamp);
+    // runs the days in order, the portfolio and timers carry over from one day to the next;
+    // the records of a day come merged by time and are dropped once handled
+    pub fn process(&mut self, days: impl IntoIterator<Item = Result<Day, Error>>) -> Result<StrategyResult, Error> {
+        let start = SystemTime::now();
 
-        limit_pending_orders
-    }
+        let mut broker = Broker::new(&self.config, self.fee_model.as_ref());
+        let strategy = &mut self.strategy;
+        let mut phases = BTreeMap::new();
+        let mut daily = Vec::new();
+        for day in days {
+            let day = day?;
+            let first_fill = broker.fills().len();
+            let realized_pnl = broker.portfolio().total_realized_pnl();
 
-    fn close_all_orders(&self, pending_orders: Arc<Mutex<Vec<(usize, Order)>>>) -> (Arc<Mutex<Vec<Order>>>, Arc<Mutex<Vec<Order>>>) {
-        let active_traded_orders = Arc::new(Mutex::new(Vec::new()));
-        let passive_traded_orders = Arc::new(Mutex::new(Vec::new()));
-        pending_orders
-            .lock()
-            .unwrap()
-            .par_iter()
-            .for_each(|(index, pending_order)| {
-                let active_traded_orders = active_traded_orders.clone();
-                let passive_traded_orders = passive_traded_orders.clone();
-                let mut current_volume = pending_order.volume;
-                let mut ticks_iter = self.ticks[*index..].iter().peekable();
-                loop {
-                    if current_volume == 0 {
-                        break;
-                    }
-                    if let Some(tick) = ticks_iter.next() {
-                        // sell by market
-                        if tick.timestamp > pending_order.timestamp + self.config.close_waiting_elapsed {
-                            match tick.handle_market_order(current_volume, Direction::Sell) {
-                                Ok((price, value)) => {
-                                    let mut active_traded_orders = active_traded_orders.lock().unwrap();
-                                    active_traded_orders.push(Order {
-                                        timestamp: tick.timestamp,
-                                        price,
-                                        volume: current_volume,
-                                        value: (value as f64 * (1f64 - self.config.active_fee_ratio)) as Value,
-                                    });
-                                    break;
-                                }
-                                Err(e) => println!("{:?}", e),
-                            }
-                        }
-                        if let Some(next_tick) = ticks_iter.peek() {
-                            let tx_index =
-                                match self.transactions.binary_search_by_key(&tick.timestamp, |tx| tx.timestamp) {
-                                    Ok(i) => i + 1,
-                                    Err(i) => i,
-                                };
-                            for transaction in
-                                self.transactions
-                                    .get(tx_index..)
-                                    .unwrap_or_default()
-                                    .iter()
-                            {
-                                if current_volume == 0 || transaction.timestamp >= next_tick.timestamp {
-                                    break;
-                                }
-                                let rest_volume = tick.handle_limit_order_by_transaction(
-                                    pending_order.price,
-                                    current_volume,
-                                    Direction::Sell,
-                                    transaction,
-                                );
-                                if rest_volume < current_volume {
-                                    let mut passive_traded_orders = passive_traded_orders.lock().unwrap();
-                                    let volume = current_volume - rest_volume;
-                                    let value = ((pending_order.price * volume) as f64 * (1f64 - self.config.passive_fee_ratio)) as Value;
-                                    passive_traded_orders.push(Order {
-                                        timestamp: transaction.timestamp,
-                                        price: pending_order.price,
-                                        volume,
-                                        value,
-                                    });
-                                    current_volume = rest_volume;
-                                }
-                            }
-                        } else {
-                            break;
+            for record in day.records {
+                let record = record?;
+                let event = record.event();
+                broker.update(event);
+                while let Some(token) = broker.pop_due_timer() {
+                    strategy.on_timer(&mut broker, token);
+                    Self::dispatch_fills(strategy, &mut broker);
+                }
+                match event {
+                    Event::Tick(tick) => {
+                        if phases.insert(tick.symbol.clone(), tick.phase) != Some(tick.phase) {
+                            strategy.on_phase(&mut broker, &tick.symbol, tick.phase);
+                            Self::dispatch_fills(strategy, &mut broker);
                         }
-                    } else {
-                        break;
+                        broker.retry_market_orders(&tick.symbol);
+                        Self::dispatch_fills(strategy, &mut broker);
+                        strategy.on_tick(&mut broker, tick);
+                    }
+                    Event::Transaction(transaction) => {
+                        broker.match_transaction(transaction);
+                        strategy.on_transaction(&mut broker, transaction);
+                    }
+                    Event::Cancel(transaction) => {
+                        broker.apply_cancel(transaction);
+                        strategy.on_cancel(&mut broker, transaction);
                     }
                 }
-            });
-
-        active_traded_orders
-            .lock()
-            .unwrap()
-            .sort_by_key(|order| order.timestamp);
-            
-        passive_traded_orders
-            .lock()
-            .unwrap()
-            .sort_by_key(|order| order.timestamp);
-
-        (active_traded_orders, passive_traded_orders)
-    }
+                Self::dispatch_fills(strategy, &mut broker);
+            }
 
-    pub fn process(&self) -> StrategyResult {
-        let start = SystemTime::now();
+            strategy.on_day_end(&mut broker);
+            Self::dispatch_fills(strategy, &mut broker);
+            broker.end_day();
+            daily.push(DailyResult::new(day.date, &broker, first_fill, realized_pnl));
+        }
 
-        let market_open_orders = self.open_market_orders();
-        let pending_orders = self.close_limit_pending_orders(&market_open_orders);
-        let (active_traded_orders, passive_traded_orders) = self.close_all_orders(pending_orders);
-
-        let active_traded_orders = active_traded_orders.lock().unwrap();
-        let passive_traded_orders = passive_traded_orders.lock().unwrap();
-        let elapsed = SystemTime::now().duration_since(start).unwrap();
-        StrategyResult::new(
-            &market_open_orders,
-            &active_traded_orders,
-            &passive_traded_orders,
-            elapsed,
-        )
+        let elapsed = SystemTime::now().duration_since(start)?;
+        Ok(StrategyResult::new(&broker, daily, elapsed))
     }
-}
\ No newline at end of file
+}
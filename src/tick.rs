@@ -1,142 +1,117 @@
-use std::ops::Neg;
-
 use anyhow::{anyhow, Error};
-use crate::transaction::Transaction;
-use crate::utils::{time_unparser, Price, Volume, Value, Time, Direction};
+use chrono::TimeDelta;
+use crate::session::Phase;
+use crate::utils::{time_unparser, Price, Volume, Symbol, Time, Direction, PRICE_TICK};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Tick {
+    pub symbol: Symbol,
     pub timestamp: Time,
     pub new_price: Price,
     pub asks: Vec<(Price, Volume)>,
     pub bids: Vec<(Price, Volume)>,
     pub high_limited: Price,
     pub low_limited: Price,
+    // as the feed reports it, continuous without a status, until `Calendar::assign_phase` refines it
+    pub phase: Phase,
 }
 
-const AM_START: Time = 34200000;
-const AM_END: Time = 41400000;
-const PM_START: Time = 46800000;
-const PM_END: Time = 54000000;
+// SSE price cage: buys at most 2% above the reference and sells at most 2% below, or ten ticks if wider
+const CAGE_RATIO: f64 = 0.02;
+const CAGE_TICKS: Price = 10;
 
 impl Tick {
-    pub fn time_eplased(&self, other: &Self) -> Time {
+    #[allow(dead_code)]
+    pub fn time_eplased(&self, other: &Self) -> TimeDelta {
         self.timestamp - other.timestamp
     }
 
     pub fn in_trading_time(&self) -> bool {
-        (self.timestamp >= AM_START && self.timestamp <= AM_END) ||
-            (self.timestamp >= PM_START && self.timestamp <= PM_END)
+        self.phase == Phase::Continuous
     }
 
-    #[allow(dead_code)]
-    pub fn is_price_valid(&self, price: Price, direction: Direction) -> bool {
-        match direction {
-            Direction::Buy => price < self.high_limited,
-            Direction::Sell => price > self.low_limited,
+    // during auctions level 2 holds the volume left unmatched at the indicative price
+    pub fn unmatched_side(&self) -> Option<Direction> {
+        let unmatched = |levels: &[(Price, Volume)]| matches!(levels.get(1), Some((0, volume)) if *volume > 0);
+        match (unmatched(&self.bids), unmatched(&self.asks)) {
+            (true, _) => Some(Direction::Buy),
+            (_, true) => Some(Direction::Sell),
+            _ => None,
         }
     }
 
-    pub fn get_first_ask_price(&self) -> Option<Price> {
-        self.asks.first().map(|(price, _)| *price)
-    }
-
-    #[allow(dead_code)]
-    pub fn get_first_bid_price(&self) -> Option<Price> {
-        self.bids.first().map(|(price, _)| *price)
+    // the price an order of `direction` is benchmarked against by the price cage:
+    // the best counterparty price, else the best own side price, else the last price
+    fn cage_reference(&self, direction: Direction) -> Option<Price> {
+        let (counterparty, own) = match direction {
+            Direction::Buy => (self.get_first_ask_price(), self.get_first_bid_price()),
+            Direction::Sell => (self.get_first_bid_price(), self.get_first_ask_price()),
+            Direction::Unknown => return None,
+        };
+        counterparty.or(own).or(Some(self.new_price).filter(|price| *price > 0))
     }
 
-    pub fn handle_limit_order_by_transaction(
-        &self,
-        price: Price,
-        volume: Volume,
-        direction: Direction,
-        transaction: &Transaction,
-    ) -> Volume {
-        assert_ne!(volume, 0, "volume of limit order should not be zero");
-        match direction {
-            Direction::Buy => {
-                match transaction.direction {
-                    Direction::Buy => volume,
-                    Direction::Sell => {
-                        let mut orders = self.asks.clone();
-                        let special_idx = orders.binary_search_by_key(&price, |(p, _)| *p);
-                        match special_idx {
-                            Ok(index) => {
-                                orders[index].1 += volume;
-                                let volume_before = orders[index].1;
-                                transaction.handle(&mut orders);
-
-                                (orders[index].1 * volume) / volume_before
-                            }
-                            Err(index) => {
-                                orders.insert(index, (price, volume));
-                                transaction.handle(&mut orders);
-
-                                orders[index].1
-                            }
-                        }
-                    }
-                }
+    // the prices an order of `direction` may take: within the daily limits and,
+    // in continuous trading, within the price cage if `cage` is on
+    pub fn price_range(&self, direction: Direction, cage: bool) -> (Price, Price) {
+        let mut low = self.low_limited;
+        let mut high = match self.high_limited {
+            0 => Price::MAX,
+            high => high,
+        };
+        if let Some(reference) = self.cage_reference(direction).filter(|_| cage && self.in_trading_time()) {
+            let width = ((reference as f64 * CAGE_RATIO) as Price).max(CAGE_TICKS * PRICE_TICK);
+            match direction {
+                Direction::Buy => high = high.min(reference + width),
+                Direction::Sell => low = low.max(reference.saturating_sub(width)),
+                Direction::Unknown => {}
             }
-            Direction::Sell => {
-                match transaction.direction {
-                    Direction::Buy => {
-                        let prices = self.bids
-                            .iter()
-                            .map(|(p, _)| (*p as isize).neg())
-                            .collect::<Vec<_>>();
-                        let mut orders = self.bids.clone();
-                        match prices.binary_search_by_key(&(price as isize).neg(), |p| *p) {
-                            Ok(index) => {
-                                orders[index].1 += volume;
-                                let volume_before = orders[index].1;
-                                transaction.handle(&mut orders);
-                                
-                                (orders[index].1 * volume) / volume_before
-                            }
-                            Err(index) => {
-                                orders.insert(index, (price, volume));
-                                transaction.handle(&mut orders);
+        }
 
-                                orders[index].1
-                            }
-                        }
-                    }
-                    Direction::Sell => volume,
-                }
-            }
+        (low, high)
+    }
+
+    pub fn check_price(&self, price: Price, direction: Direction, cage: bool) -> Result<(), Error> {
+        let (low, high) = self.price_range(direction, cage);
+        if direction == Direction::Unknown || price < low || price > high {
+            return Err(anyhow!(
+                "{:?} order at {} is outside {} - {} at {}",
+                direction, price, low, high, time_unparser(self.timestamp),
+            ));
         }
+
+        Ok(())
     }
 
-    pub fn handle_market_order(
-        &self,
-        volume: usize,
-        direction: Direction,
-    ) -> Result<(Price, Value), Error> {
-        assert_ne!(volume, 0, "volume of market order should not be zero");
-        if !self.in_trading_time() {
-            return Err(anyhow!("market order at {} is not in trading time", time_unparser(self.timestamp)));
+    pub fn limit_price(&self, direction: Direction) -> Option<Price> {
+        match direction {
+            Direction::Buy => Some(self.high_limited),
+            Direction::Sell => Some(self.low_limited),
+            Direction::Unknown => None,
         }
+        .filter(|price| *price > 0)
+    }
 
-        let orders_iter = match direction {
-            Direction::Buy => self.asks.iter(),
-            Direction::Sell => self.bids.iter(),
+    // sealed at the limit against `direction`: nothing left to take and the price stands at the limit,
+    // e.g. no asks at limit-up for a buy
+    pub fn sealed(&self, direction: Direction) -> bool {
+        let (counterparty, own) = match direction {
+            Direction::Buy => (self.get_first_ask_price(), self.get_first_bid_price()),
+            Direction::Sell => (self.get_first_bid_price(), self.get_first_ask_price()),
+            Direction::Unknown => return false,
         };
-
-        let mut value = 0;
-        let mut left_volume = volume;
-        for (p, v) in orders_iter {
-            if v >= &left_volume {
-                value += p * left_volume;
-                break;
-            } else {
-                left_volume -= v;
-                value += p * v;
-            }
+        match self.limit_price(direction) {
+            Some(limit) => counterparty.is_none() && (own == Some(limit) || self.new_price == limit),
+            None => false,
         }
+    }
 
-        Ok((value / volume, value))
+    // a zero price means the side is empty
+    pub fn get_first_ask_price(&self) -> Option<Price> {
+        self.asks.first().map(|(price, _)| *price).filter(|price| *price > 0)
     }
-}
 
+    pub fn get_first_bid_price(&self) -> Option<Price> {
+        self.bids.first().map(|(price, _)| *price).filter(|price| *price > 0)
+    }
+}
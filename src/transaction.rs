@@ -1,34 +1,132 @@
-use crate::utils::{Direction, Price, Volume};
+use std::convert::TryFrom;
+use anyhow::{anyhow, Error};
+
+use crate::utils::{Direction, Symbol, Time};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderKind {
+    Unspecified,
+    Market,
+    Limit,
+    // best price on the own side (SZSE)
+    OwnBest,
+    Other(char),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionCode {
+    Trade,
+    // order cancellation, SZSE style feeds only
+    Cancel,
+}
+
+// feeds carry these codes either as the character itself or as its ASCII code
+fn parse_code(value: &str) -> Result<char, Error> {
+    let value = value.trim();
+    match value.parse::<u8>() {
+        Ok(code) if code >= 10 => Ok(code as char),
+        _ => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (None, _) => Ok('0'),
+                (Some(c), None) => Ok(c),
+                _ => Err(anyhow!("unexpected code `{}`", value)),
+            }
+        }
+    }
+}
+
+impl OrderKind {
+    pub fn from_code(code: char) -> Self {
+        match code {
+            '0' => Self::Unspecified,
+            '1' => Self::Market,
+            '2' => Self::Limit,
+            'U' => Self::OwnBest,
+            c => Self::Other(c),
+        }
+    }
+
+    pub fn code(&self) -> char {
+        match self {
+            Self::Unspecified => '0',
+            Self::Market => '1',
+            Self::Limit => '2',
+            Self::OwnBest => 'U',
+            Self::Other(c) => *c,
+        }
+    }
+}
+
+impl TryFrom<&str> for OrderKind {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Ok(Self::from_code(parse_code(value)?))
+    }
+}
+
+impl FunctionCode {
+    pub fn from_code(code: char) -> Self {
+        match code {
+            'C' => Self::Cancel,
+            _ => Self::Trade,
+        }
+    }
+
+    pub fn code(&self) -> char {
+        match self {
+            Self::Trade => '0',
+            Self::Cancel => 'C',
+        }
+    }
+}
+
+impl TryFrom<&str> for FunctionCode {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Ok(Self::from_code(parse_code(value)?))
+    }
+}
 
 #[derive(Debug)]
 pub struct Transaction {
-    pub timestamp: i64,
+    pub symbol: Symbol,
+    pub timestamp: Time,
     pub index: usize,
     pub price: usize,
     pub volume: usize,
     pub direction: Direction,
+    #[allow(dead_code)]
+    pub order_kind: OrderKind,
+    pub function_code: FunctionCode,
+    // exchange ids of the orders on each side, 0 if not given
+    pub ask_order: usize,
+    pub bid_order: usize,
 }
 
 impl Transaction {
-    pub fn handle(
-        &self,
-        orders: &mut [(Price, Volume)],
-    ) {
-        let mut trx_volume = self.volume;
-        for (price, volume) in orders.iter_mut() {
-            if match self.direction {
-                Direction::Buy => &self.price < price,
-                Direction::Sell => &self.price > price,
-            } {
-                break;
-            }
-            if &trx_volume > volume {
-                trx_volume -= *volume;
-                *volume = 0;
-            } else {
-                *volume -= trx_volume;
-                break;
-            }
+    pub fn is_cancel(&self) -> bool {
+        self.function_code == FunctionCode::Cancel
+    }
+
+    // the side a cancellation removed volume from
+    pub fn cancel_side(&self) -> Direction {
+        match (self.ask_order, self.bid_order) {
+            (0, 0) => Direction::Unknown,
+            (_, 0) => Direction::Sell,
+            (0, _) => Direction::Buy,
+            _ => Direction::Unknown,
+        }
+    }
+
+    // the side that took liquidity from orders resting on `side`,
+    // auction trades take it from both sides
+    pub fn aggressor(&self, side: Direction) -> Direction {
+        match self.direction {
+            Direction::Unknown => side.opposite(),
+            direction => direction,
         }
     }
 }
@@ -1,25 +1,69 @@
+use std::convert::TryFrom;
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Serializer;
+use serde::Serialize;
+
 pub type Price = usize;
 pub type Volume = usize;
 pub type Value = usize;
-pub type Time = i64;
+// exchange time, Asia/Shanghai
+pub type Time = DateTime<Tz>;
+pub type Pnl = i64;
+// Wind code, e.g. `601012.SH`
+pub type Symbol = String;
+
+// prices are quoted in 1/10000 CNY
+pub const PRICE_SCALE: Value = 10000;
+// the minimum price step, 0.01 CNY
+pub const PRICE_TICK: Price = 100;
+
+pub const TIMEZONE: Tz = chrono_tz::Asia::Shanghai;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum Direction {
     Buy,
     Sell,
+    // trades without an aggressor side, e.g. call auction trades
+    Unknown,
+}
+
+impl Direction {
+    pub fn opposite(&self) -> Self {
+        match self {
+            Direction::Buy => Direction::Sell,
+            Direction::Sell => Direction::Buy,
+            Direction::Unknown => Direction::Unknown,
+        }
+    }
+
+    // the `BSFlag` of the side
+    pub fn code(&self) -> char {
+        match self {
+            Direction::Buy => 'B',
+            Direction::Sell => 'S',
+            Direction::Unknown => 'N',
+        }
+    }
 }
 
-impl From<&str> for Direction {
-    fn from(value: &str) -> Self {
-        match value {
-            "B" => Self::Buy,
-            "S" => Self::Sell,
-            _ => panic!("unexpected direction"),
+impl TryFrom<&str> for Direction {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim() {
+            "B" => Ok(Self::Buy),
+            "S" => Ok(Self::Sell),
+            "" | "N" => Ok(Self::Unknown),
+            _ => Err(anyhow!("unexpected direction `{}`", value)),
         }
     }
 }
 
-pub fn time_parser(mut t: usize) -> Time {
+// `t` is HHMMSSmmm on the trading `date` in exchange time, `Calendar::timestamp` moves night session rows off it
+pub fn time_parser(date: NaiveDate, n_time: usize) -> Result<Time, Error> {
+    let mut t = n_time;
     let m_secs = t % 1000;
     t /= 1000;
     let secs = t % 100;
@@ -28,17 +72,34 @@ pub fn time_parser(mut t: usize) -> Time {
     t /= 100;
     let hours = t;
 
-    ((hours * 3600 + mins * 60 + secs) * 1000 + m_secs) as Time
+    NaiveTime::from_hms_milli_opt(hours as u32, mins as u32, secs as u32, m_secs as u32)
+        .and_then(|time| TIMEZONE.from_local_datetime(&date.and_time(time)).single())
+        .ok_or_else(|| anyhow!("invalid time {} on {}", n_time, date))
 }
 
-pub fn time_unparser(mut t: Time) -> usize {
-    let m_secs = t % 1000;
-    t /= 1000;
-    let hours = t / 3600;
-    t = t % 3600;
-    let mins = t / 60;
-    t = t % 60;
-    let secs = t ;
-
-    ((hours * 10000 + mins * 100 + secs) * 1000 + m_secs) as usize
-}
\ No newline at end of file
+pub fn time_unparser(t: Time) -> String {
+    t.format("%Y-%m-%d %H:%M:%S%.3f%:z").to_string()
+}
+
+// earlier than any data
+pub fn time_origin() -> Time {
+    DateTime::<Utc>::MIN_UTC.with_timezone(&TIMEZONE)
+}
+
+pub fn serialize_time<S: Serializer>(t: &Time, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time_unparser(*t))
+}
+
+/// A time of day range, from inclusive and to exclusive, open where not given.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeRange {
+    pub from: Option<NaiveTime>,
+    pub to: Option<NaiveTime>,
+}
+
+impl TimeRange {
+    pub fn contains(&self, time: Time) -> bool {
+        let time = time.time();
+        self.from.is_none_or(|from| time >= from) && self.to.is_none_or(|to| time < to)
+    }
+}
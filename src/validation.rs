@@ -0,0 +1,458 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use crate::session::Phase;
+use crate::tick::Tick;
+use crate::transaction::Transaction;
+use crate::utils::{time_unparser, Price, Time, Volume};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    // remove the row and go on
+    Drop,
+    // keep the row and report it
+    Warn,
+    // report the row and refuse to run
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    NonMonotonicTime,
+    CrossedBook,
+    ZeroPriceLevel,
+    UnsortedLadder,
+    PriceOutOfLimits,
+    TransactionOutOfRange,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Check::NonMonotonicTime => "non_monotonic_time",
+            Check::CrossedBook => "crossed_book",
+            Check::ZeroPriceLevel => "zero_price_level",
+            Check::UnsortedLadder => "unsorted_ladder",
+            Check::PriceOutOfLimits => "price_out_of_limits",
+            Check::TransactionOutOfRange => "transaction_out_of_range",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ValidationConfig {
+    pub non_monotonic_time: Action,
+    pub crossed_book: Action,
+    pub zero_price_level: Action,
+    pub unsorted_ladder: Action,
+    pub price_out_of_limits: Action,
+    pub transaction_out_of_range: Action,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            non_monotonic_time: Action::Warn,
+            crossed_book: Action::Warn,
+            zero_price_level: Action::Drop,
+            unsorted_ladder: Action::Drop,
+            price_out_of_limits: Action::Drop,
+            transaction_out_of_range: Action::Warn,
+        }
+    }
+}
+
+impl ValidationConfig {
+    fn action(&self, check: Check) -> Action {
+        match check {
+            Check::NonMonotonicTime => self.non_monotonic_time,
+            Check::CrossedBook => self.crossed_book,
+            Check::ZeroPriceLevel => self.zero_price_level,
+            Check::UnsortedLadder => self.unsorted_ladder,
+            Check::PriceOutOfLimits => self.price_out_of_limits,
+            Check::TransactionOutOfRange => self.transaction_out_of_range,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Issue {
+    pub source: &'static str,
+    // line in the source file, the header being line 1
+    pub line: usize,
+    pub check: Check,
+    pub action: Action,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} line {}: {}: {} ({:?})", self.source, self.line, self.check, self.message, self.action)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    pub fn failed(&self) -> bool {
+        self.issues.iter().any(|issue| issue.action == Action::Fail)
+    }
+
+    pub fn count(&self, check: Check, action: Action) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.check == check && issue.action == action)
+            .count()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let checks = [
+            Check::NonMonotonicTime,
+            Check::CrossedBook,
+            Check::ZeroPriceLevel,
+            Check::UnsortedLadder,
+            Check::PriceOutOfLimits,
+            Check::TransactionOutOfRange,
+        ];
+        writeln!(f, "[Validation]")?;
+        for check in checks.iter() {
+            let (dropped, warned, failed) = (
+                self.count(*check, Action::Drop),
+                self.count(*check, Action::Warn),
+                self.count(*check, Action::Fail),
+            );
+            if dropped + warned + failed > 0 {
+                writeln!(f, "{}: dropped {}, warned {}, failed {}", check, dropped, warned, failed)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// levels of a ladder up to the first empty one
+fn quoted_levels(levels: &[(Price, Volume)]) -> &[(Price, Volume)] {
+    let depth = levels
+        .iter()
+        .rposition(|(price, volume)| *price > 0 || *volume > 0)
+        .map_or(0, |index| index + 1);
+    &levels[..depth]
+}
+
+fn check_ladder(levels: &[(Price, Volume)], ascending: bool) -> Option<(Check, String)> {
+    let levels = quoted_levels(levels);
+    if let Some(index) = levels.iter().position(|(price, _)| *price == 0) {
+        return Some((Check::ZeroPriceLevel, format!("level {} has no price", index + 1)));
+    }
+    let sorted = levels.windows(2).all(|pair| match ascending {
+        true => pair[0].0 < pair[1].0,
+        false => pair[0].0 > pair[1].0,
+    });
+    if !sorted {
+        return Some((Check::UnsortedLadder, format!("{} ladder is not sorted", if ascending { "ask" } else { "bid" })));
+    }
+
+    None
+}
+
+// `last` is the time of the last kept snapshot
+fn check_tick(tick: &Tick, last: Option<Time>) -> Vec<(Check, String)> {
+    let mut problems = Vec::new();
+    if let Some(last) = last {
+        if tick.timestamp < last {
+            problems.push((
+                Check::NonMonotonicTime,
+                format!("{} is before {}", time_unparser(tick.timestamp), time_unparser(last)),
+            ));
+        }
+    }
+    // auction snapshots and the one after the close show the indicative price on both sides
+    // and the unmatched volume at price 0 on level 2
+    if !matches!(tick.phase, Phase::OpeningAuction | Phase::ClosingAuction | Phase::Closed) {
+        problems.extend(check_ladder(&tick.asks, true));
+        problems.extend(check_ladder(&tick.bids, false));
+        if let (Some(ask), Some(bid)) = (tick.get_first_ask_price(), tick.get_first_bid_price()) {
+            if bid >= ask {
+                problems.push((Check::CrossedBook, format!("bid1 {} >= ask1 {}", bid, ask)));
+            }
+        }
+    }
+    // a limit of 0 is not given by the feed
+    let out_of_limits = std::iter::once(tick.new_price)
+        .chain(tick.asks.iter().chain(tick.bids.iter()).map(|(price, _)| *price))
+        .filter(|price| *price > 0)
+        .find(|price| (tick.high_limited > 0 && *price > tick.high_limited) || *price < tick.low_limited);
+    if let Some(price) = out_of_limits {
+        problems.push((
+            Check::PriceOutOfLimits,
+            format!("price {} is outside {} - {}", price, tick.low_limited, tick.high_limited),
+        ));
+    }
+
+    problems
+}
+
+// the prices a snapshot shows trading or quoted, from the deepest bid to the deepest ask and the last price,
+// open on a side without levels
+fn quoted_range(tick: &Tick) -> (Price, Price) {
+    let last = Some(tick.new_price).filter(|price| *price > 0);
+    let low = tick.bids.iter().map(|(price, _)| *price).filter(|price| *price > 0).min();
+    let high = tick.asks.iter().map(|(price, _)| *price).filter(|price| *price > 0).max();
+
+    (
+        low.map_or(0, |low| last.map_or(low, |last| low.min(last))),
+        high.map_or(Price::MAX, |high| last.map_or(high, |last| high.max(last))),
+    )
+}
+
+// `last` is the time and index of the last kept transaction,
+// `range` the quoted range of the latest snapshot at or before it
+fn check_transaction(
+    transaction: &Transaction,
+    last: Option<(Time, usize)>,
+    range: Option<(Price, Price)>,
+) -> Vec<(Check, String)> {
+    let mut problems = Vec::new();
+    if let Some((timestamp, index)) = last {
+        if (transaction.timestamp, transaction.index) < (timestamp, index) {
+            problems.push((
+                Check::NonMonotonicTime,
+                format!("#{} at {} is before #{} at {}",
+                    transaction.index, time_unparser(transaction.timestamp),
+                    index, time_unparser(timestamp)),
+            ));
+        }
+    }
+    // cancellations carry no price
+    if let Some((low, high)) = range.filter(|_| !transaction.is_cancel()) {
+        if transaction.price > high || transaction.price < low {
+            problems.push((
+                Check::TransactionOutOfRange,
+                format!("price {} is outside the book {} - {}", transaction.price, low, high),
+            ));
+        }
+    }
+
+    problems
+}
+
+// records the problems of one row and tells whether to keep it
+fn report_row(
+    report: &mut ValidationReport,
+    config: &ValidationConfig,
+    source: &'static str,
+    line: usize,
+    problems: Vec<(Check, String)>,
+) -> bool {
+    let mut keep = true;
+    for (check, message) in problems {
+        let action = config.action(check);
+        keep &= action != Action::Drop;
+        report.issues.push(Issue {
+            source,
+            line,
+            check,
+            action,
+            message,
+        });
+    }
+
+    keep
+}
+
+/// Checks the ticks and transactions of one symbol as they are read, in time order,
+/// keeping only what the next row is checked against.
+#[derive(Debug)]
+pub struct Validator {
+    config: ValidationConfig,
+    report: ValidationReport,
+    tick_rows: usize,
+    transaction_rows: usize,
+    last_tick: Option<Time>,
+    last_transaction: Option<(Time, usize)>,
+    // the quoted range of the latest kept snapshot
+    range: Option<(Price, Price)>,
+}
+
+impl Validator {
+    pub fn new(config: &ValidationConfig) -> Self {
+        Self {
+            config: config.clone(),
+            report: ValidationReport::default(),
+            tick_rows: 0,
+            transaction_rows: 0,
+            last_tick: None,
+            last_transaction: None,
+            range: None,
+        }
+    }
+
+    pub fn report(&self) -> &ValidationReport {
+        &self.report
+    }
+
+    // tells whether to keep the snapshot
+    pub fn tick(&mut self, tick: &Tick) -> bool {
+        self.tick_rows += 1;
+        let problems = check_tick(tick, self.last_tick);
+        let keep = report_row(&mut self.report, &self.config, "ticks", self.tick_rows + 1, problems);
+        if keep {
+            self.last_tick = Some(tick.timestamp);
+            self.range = Some(quoted_range(tick));
+        }
+
+        keep
+    }
+
+    // tells whether to keep the transaction, snapshots stamped with its time should be checked before it
+    pub fn transaction(&mut self, transaction: &Transaction) -> bool {
+        self.transaction_rows += 1;
+        let problems = check_transaction(transaction, self.last_transaction, self.range);
+        let keep = report_row(&mut self.report, &self.config, "transactions", self.transaction_rows + 1, problems);
+        if keep {
+            self.last_transaction = Some((transaction.timestamp, transaction.index));
+        }
+
+        keep
+    }
+}
+
+/// A row failed a check set to `fail`.
+#[derive(Debug)]
+pub struct ValidationFailed(pub String);
+
+impl fmt::Display for ValidationFailed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid data in {}", self.0)
+    }
+}
+
+impl std::error::Error for ValidationFailed {}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::transaction::{FunctionCode, OrderKind};
+    use crate::utils::{Direction, TIMEZONE};
+
+    fn tick(second: u32) -> Tick {
+        Tick {
+            symbol: "601012.SH".to_string(),
+            timestamp: TIMEZONE.with_ymd_and_hms(2021, 6, 4, 10, 0, second).unwrap(),
+            new_price: 500_000,
+            asks: vec![(500_100, 100), (500_200, 200), (0, 0)],
+            bids: vec![(500_000, 100), (499_900, 200), (0, 0)],
+            high_limited: 550_000,
+            low_limited: 450_000,
+            phase: Phase::Continuous,
+        }
+    }
+
+    fn transaction(second: u32, index: usize, price: Price) -> Transaction {
+        Transaction {
+            symbol: "601012.SH".to_string(),
+            timestamp: TIMEZONE.with_ymd_and_hms(2021, 6, 4, 10, 0, second).unwrap(),
+            index,
+            price,
+            volume: 100,
+            direction: Direction::Buy,
+            order_kind: OrderKind::Unspecified,
+            function_code: FunctionCode::Trade,
+            ask_order: 0,
+            bid_order: 0,
+        }
+    }
+
+    fn checks(problems: Vec<(Check, String)>) -> Vec<Check> {
+        problems.into_iter().map(|(check, _)| check).collect()
+    }
+
+    #[test]
+    fn ticks_are_checked() {
+        let last = Some(tick(1).timestamp);
+        assert!(check_tick(&tick(1), last).is_empty());
+        assert_eq!(checks(check_tick(&tick(0), last)), vec![Check::NonMonotonicTime]);
+
+        let mut crossed = tick(1);
+        crossed.bids[0].0 = 500_100;
+        assert_eq!(checks(check_tick(&crossed, None)), vec![Check::CrossedBook]);
+        // auction snapshots show the indicative price on both sides
+        crossed.phase = Phase::OpeningAuction;
+        assert!(check_tick(&crossed, None).is_empty());
+
+        let mut gap = tick(1);
+        gap.asks[1].0 = 0;
+        gap.asks[2] = (500_300, 100);
+        assert_eq!(checks(check_tick(&gap, None)), vec![Check::ZeroPriceLevel]);
+
+        let mut unsorted = tick(1);
+        unsorted.bids.swap(0, 1);
+        assert_eq!(checks(check_tick(&unsorted, None)), vec![Check::UnsortedLadder]);
+
+        let mut limited = tick(1);
+        limited.high_limited = 500_150;
+        assert_eq!(checks(check_tick(&limited, None)), vec![Check::PriceOutOfLimits]);
+        limited.low_limited = 500_050;
+        limited.high_limited = 0;
+        assert_eq!(checks(check_tick(&limited, None)), vec![Check::PriceOutOfLimits]);
+        // limits the feed does not give are not checked
+        limited.low_limited = 0;
+        assert!(check_tick(&limited, None).is_empty());
+    }
+
+    #[test]
+    fn transactions_are_checked() {
+        let range = Some(quoted_range(&tick(0)));
+        assert_eq!(range, Some((499_900, 500_200)));
+        let last = Some((TIMEZONE.with_ymd_and_hms(2021, 6, 4, 10, 0, 1).unwrap(), 5));
+        assert!(check_transaction(&transaction(1, 6, 500_200), last, range).is_empty());
+        assert_eq!(checks(check_transaction(&transaction(1, 4, 500_000), last, range)), vec![Check::NonMonotonicTime]);
+        assert_eq!(checks(check_transaction(&transaction(1, 6, 500_300), last, range)), vec![Check::TransactionOutOfRange]);
+        // before the first snapshot and for cancellations there is nothing to check against
+        assert!(check_transaction(&transaction(1, 6, 500_300), last, None).is_empty());
+        let mut cancel = transaction(1, 6, 0);
+        cancel.function_code = FunctionCode::Cancel;
+        assert!(check_transaction(&cancel, last, range).is_empty());
+    }
+
+    #[test]
+    fn actions_decide_what_is_kept() {
+        let mut crossed = tick(1);
+        crossed.bids[0].0 = 500_100;
+        crossed.asks[1].0 = 500_400;
+        let run = |action: Action| {
+            let config = ValidationConfig { crossed_book: action, ..ValidationConfig::default() };
+            let mut validator = Validator::new(&config);
+            let kept = (validator.tick(&tick(0)), validator.tick(&crossed));
+            // a transaction is checked against the last snapshot kept
+            validator.transaction(&transaction(1, 1, 500_300));
+            (kept, validator.report)
+        };
+
+        let (kept, report) = run(Action::Drop);
+        assert_eq!(kept, (true, false));
+        assert_eq!(report.count(Check::CrossedBook, Action::Drop), 1);
+        assert_eq!(report.count(Check::TransactionOutOfRange, Action::Warn), 1);
+        assert!(!report.failed());
+
+        let (kept, report) = run(Action::Warn);
+        assert_eq!(kept, (true, true));
+        assert_eq!(report.count(Check::CrossedBook, Action::Warn), 1);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].line, 3);
+
+        let (kept, report) = run(Action::Fail);
+        assert_eq!(kept, (true, true));
+        assert!(report.failed());
+    }
+}